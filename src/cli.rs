@@ -0,0 +1,536 @@
+use raytracing_with_rust::raytrace::filter::FilterKind;
+use raytracing_with_rust::raytrace::integrator::Integrator;
+use raytracing_with_rust::raytrace::sampler::SamplerKind;
+use raytracing_with_rust::raytrace::sky::SkySettings;
+use raytracing_with_rust::raytrace::tiles::TileOrder;
+use raytracing_with_rust::raytrace::tonemap::ToneMapper;
+use raytracing_with_rust::raytrace::vec::Vec3;
+use raytracing_with_rust::scenes::SCENE_NAMES;
+
+use std::str::FromStr;
+use std::time::Duration;
+
+pub const USAGE: &str = "\
+Usage: raytracing_with_rust [OPTIONS] [SCENE]
+
+Options:
+  -s, --scene <NAME>         Built-in scene to render (default: random_scene)
+  -w, --width <N>            Image width in pixels (default: 500)
+  -H, --height <N>           Image height in pixels (default: 500)
+  -n, --spp <N>              Samples per pixel (default: 10)
+      --max-spp <N>          Enable adaptive sampling: keep adding samples to noisy pixels,
+                             up to N; --spp becomes the minimum
+      --error-threshold <E>  Relative error at which adaptive sampling stops (default: 0.05)
+      --sample-heatmap       Also write the samples taken per pixel as <output>_samples.png
+      --pass-spp <N>         Render in progressive passes of N samples per pixel
+                             (default: a single pass)
+      --save-every <N>       Write the image after every N passes (default: 1)
+      --checkpoint <PATH>    Save the accumulated samples to PATH along with the image, and
+                             resume from it if it exists
+      --time-limit <SECONDS> Keep rendering passes of --pass-spp samples (default: --spp)
+                             until the time is used up
+      --target-error <E>     Keep rendering passes until the estimated relative error of the
+                             pixels drops to E
+      --tile-size <PX>       Edge length of the square render tiles (default: 32)
+      --tile-order <NAME>    scanline, spiral or hilbert (default: spiral)
+      --clamp-indirect <L>   Limit each sample's indirect light to L per channel, trading a
+                             little energy for fewer fireflies
+      --median-of-means <K>  Accumulate samples in K buckets per pixel and keep the median
+                             of their means, rejecting outliers
+  -d, --max-depth <N>        Maximum path depth (default: 50)
+      --rr-depth <N>         Depth at which Russian roulette starts (default: 3)
+      --seed <N>             Random seed for sampling and scene generation (default: 0)
+      --integrator <NAME>    path, ao, or a debug view: shading-normal, geometric-normal,
+                             depth, uv, material-id, aabb-tests or primitive-tests
+                             (default: path)
+      --ao-samples <N>       Occlusion rays per camera ray for the ao integrator (default: 16)
+      --ao-distance <D>      Distance beyond which hits do not occlude (default: unlimited)
+      --sampler <NAME>       independent, stratified, halton or sobol (default: sobol)
+      --filter <NAME>        box, tent, gaussian, mitchell or lanczos (default: box)
+      --filter-radius <PX>   Reconstruction filter radius (default: depends on the filter)
+      --look-from <X,Y,Z>    Override the scene camera position
+      --look-at <X,Y,Z>      Override the scene camera target
+      --vfov <DEGREES>       Override the vertical field of view
+      --aperture <F>         Override the lens aperture
+      --focus-dist <F>       Override the focus distance
+  -e, --env <PATH>           Environment map, .hdr, .exr, .png or .jpg, laid out as an
+                             equirectangular panorama, angular light probe, cube-map cross
+                             or strip, or six faces named by a {face} placeholder (default:
+                             the scene's own)
+      --env-rotation <RAD>   Rotation of the environment map, either a longitude offset or
+                             X,Y,Z angles applied about the x, then y, then z axis
+      --env-intensity <S>    Multiplier for the environment map or sky radiance (default: 1)
+      --env-tint <R,G,B>     Color the environment map or sky radiance is multiplied by
+      --sky                  Light the scene with an analytic daylight sky and sun instead of
+                             an environment map
+      --sun-elevation <DEG>  Sun angle above the horizon, from 0 to 90 (default: 45)
+      --sun-azimuth <DEG>    Sun angle around the vertical from +x towards +z (default: 0)
+      --turbidity <T>        Haziness of the sky, from 2 for clear to 10 (default: 3)
+      --ground-albedo <A>    Reflectance of the ground below the horizon, a single value or
+                             R,G,B (default: 0.3)
+      --background <SPEC>    none, gradient or a constant R,G,B radiance, instead of the
+                             scene's environment
+      --camera-env <SPEC>    What camera rays that miss the scene see, leaving the lighting
+                             alone: none, gradient, R,G,B, sky, an environment map, or
+                             blur:<DEGREES> for a blurred copy of the --env map
+      --specular-env <SPEC>  Like --camera-env, for rays reflected or refracted by specular
+                             surfaces
+      --exposure <EV>        Exposure adjustment in stops for .png and .ppm (default: 0)
+      --tonemap <NAME>       linear, reinhard, extended-reinhard, aces or uncharted2
+                             (default: linear)
+      --white-point <L>      Luminance mapped to white by extended-reinhard (default: 4)
+  -o, --output <PATH>        Output image, .png, .ppm, .hdr or .pfm (default: render.png)
+      --denoise              Denoise the image using the albedo, normal and depth passes
+      --aovs                 Also write albedo, normal, position, depth, uv and object ID
+                             passes as <output>_<pass>.pfm
+      --list-scenes          Print the built-in scene names and exit
+  -h, --help                 Print this message and exit
+";
+
+// Environment given on the command line in place of the scene's, or for some kinds of ray
+// only. Maps and blurred maps are only accepted for the latter.
+#[derive(Debug)]
+pub enum Background {
+    None,
+    Gradient,
+    Constant(Vec3),
+    Map(String),
+    // The --env map blurred by this many degrees.
+    Blurred(f32),
+    Sky,
+}
+
+pub enum Command {
+    Render(Box<Options>),
+    ListScenes,
+    Help,
+}
+
+pub struct Options {
+    pub scene: String,
+    pub width: usize,
+    pub height: usize,
+    pub samples: usize,
+    pub max_samples: Option<usize>,
+    pub error_threshold: f32,
+    pub sample_heatmap: bool,
+    pub pass_samples: Option<usize>,
+    pub save_every: usize,
+    pub checkpoint: Option<String>,
+    pub time_limit: Option<Duration>,
+    pub target_error: Option<f32>,
+    pub indirect_clamp: Option<f32>,
+    pub median_of_means: Option<usize>,
+    pub tile_size: usize,
+    pub tile_order: TileOrder,
+    pub max_depth: usize,
+    pub rr_min_depth: usize,
+    pub seed: u64,
+    pub integrator: Integrator,
+    pub ao_samples: usize,
+    pub ao_distance: f32,
+    pub sampler: SamplerKind,
+    pub filter: FilterKind,
+    pub filter_radius: Option<f32>,
+    pub look_from: Option<Vec3>,
+    pub look_at: Option<Vec3>,
+    pub vfov: Option<f32>,
+    pub aperture: Option<f32>,
+    pub focus_dist: Option<f32>,
+    pub environment: Option<String>,
+    pub env_rotation: Vec3,
+    pub env_intensity: f32,
+    pub env_tint: Vec3,
+    pub sky: bool,
+    pub sky_settings: SkySettings,
+    pub background: Option<Background>,
+    pub camera_env: Option<Background>,
+    pub specular_env: Option<Background>,
+    pub exposure: f32,
+    pub tonemap: ToneMapper,
+    pub white_point: f32,
+    pub output: String,
+    pub aovs: bool,
+    pub denoise: bool,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            scene: String::from("random_scene"),
+            width: 500,
+            height: 500,
+            samples: 10,
+            max_samples: None,
+            error_threshold: 0.05,
+            sample_heatmap: false,
+            pass_samples: None,
+            save_every: 1,
+            checkpoint: None,
+            time_limit: None,
+            target_error: None,
+            indirect_clamp: None,
+            median_of_means: None,
+            tile_size: 32,
+            tile_order: TileOrder::Spiral,
+            max_depth: 50,
+            rr_min_depth: 3,
+            seed: 0,
+            integrator: Integrator::Path,
+            ao_samples: 16,
+            ao_distance: f32::MAX,
+            sampler: SamplerKind::Sobol,
+            filter: FilterKind::Box,
+            filter_radius: None,
+            look_from: None,
+            look_at: None,
+            vfov: None,
+            aperture: None,
+            focus_dist: None,
+            environment: None,
+            env_rotation: Vec3::new(0.0, 0.0, 0.0),
+            env_intensity: 1.0,
+            env_tint: Vec3::new(1.0, 1.0, 1.0),
+            sky: false,
+            sky_settings: SkySettings::default(),
+            background: None,
+            camera_env: None,
+            specular_env: None,
+            exposure: 0.0,
+            tonemap: ToneMapper::Linear,
+            white_point: 4.0,
+            output: String::from("render.png"),
+            aovs: false,
+            denoise: false,
+        }
+    }
+}
+
+pub fn parse<I: Iterator<Item = String>>(mut args: I) -> Result<Command, String> {
+    let mut options = Options::default();
+    let mut scene_set = false;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(Command::Help),
+            "--list-scenes" => return Ok(Command::ListScenes),
+            "-s" | "--scene" => {
+                options.scene = value(&arg, args.next())?;
+                scene_set = true;
+            }
+            "-w" | "--width" => options.width = parse_value(&arg, args.next())?,
+            "-H" | "--height" => options.height = parse_value(&arg, args.next())?,
+            "-n" | "--spp" => options.samples = parse_value(&arg, args.next())?,
+            "--max-spp" => options.max_samples = Some(parse_value(&arg, args.next())?),
+            "--error-threshold" => options.error_threshold = parse_value(&arg, args.next())?,
+            "--sample-heatmap" => options.sample_heatmap = true,
+            "--pass-spp" => options.pass_samples = Some(parse_value(&arg, args.next())?),
+            "--save-every" => options.save_every = parse_value(&arg, args.next())?,
+            "--checkpoint" => options.checkpoint = Some(value(&arg, args.next())?),
+            "--time-limit" => {
+                let seconds: f32 = parse_value(&arg, args.next())?;
+                options.time_limit = match Duration::try_from_secs_f32(seconds) {
+                    Ok(limit) if seconds > 0.0 => Some(limit),
+                    _ => return Err(String::from("time limit must be greater than zero")),
+                };
+            }
+            "--target-error" => options.target_error = Some(parse_value(&arg, args.next())?),
+            "--clamp-indirect" => options.indirect_clamp = Some(parse_value(&arg, args.next())?),
+            "--median-of-means" => options.median_of_means = Some(parse_value(&arg, args.next())?),
+            "--tile-size" => options.tile_size = parse_value(&arg, args.next())?,
+            "--tile-order" => {
+                let name = value(&arg, args.next())?;
+                options.tile_order = TileOrder::from_name(&name).ok_or_else(|| {
+                    format!(
+                        "unknown tile order '{}', expected one of: {}",
+                        name,
+                        TileOrder::NAMES.join(", ")
+                    )
+                })?;
+            }
+            "-d" | "--max-depth" => options.max_depth = parse_value(&arg, args.next())?,
+            "--rr-depth" => options.rr_min_depth = parse_value(&arg, args.next())?,
+            "--seed" => options.seed = parse_value(&arg, args.next())?,
+            "--integrator" => {
+                let name = value(&arg, args.next())?;
+                options.integrator = Integrator::from_name(&name).ok_or_else(|| {
+                    format!(
+                        "unknown integrator '{}', expected one of: {}",
+                        name,
+                        Integrator::NAMES.join(", ")
+                    )
+                })?;
+            }
+            "--ao-samples" => options.ao_samples = parse_value(&arg, args.next())?,
+            "--ao-distance" => options.ao_distance = parse_value(&arg, args.next())?,
+            "--sampler" => {
+                let name = value(&arg, args.next())?;
+                options.sampler = SamplerKind::from_name(&name).ok_or_else(|| {
+                    format!(
+                        "unknown sampler '{}', expected one of: {}",
+                        name,
+                        SamplerKind::NAMES.join(", ")
+                    )
+                })?;
+            }
+            "--filter" => {
+                let name = value(&arg, args.next())?;
+                options.filter = FilterKind::from_name(&name).ok_or_else(|| {
+                    format!(
+                        "unknown filter '{}', expected one of: {}",
+                        name,
+                        FilterKind::NAMES.join(", ")
+                    )
+                })?;
+            }
+            "--filter-radius" => options.filter_radius = Some(parse_value(&arg, args.next())?),
+            "--look-from" => options.look_from = Some(parse_vec3(&arg, args.next())?),
+            "--look-at" => options.look_at = Some(parse_vec3(&arg, args.next())?),
+            "--vfov" => options.vfov = Some(parse_value(&arg, args.next())?),
+            "--aperture" => options.aperture = Some(parse_value(&arg, args.next())?),
+            "--focus-dist" => options.focus_dist = Some(parse_value(&arg, args.next())?),
+            "-e" | "--env" => options.environment = Some(value(&arg, args.next())?),
+            "--env-rotation" => {
+                let spec = value(&arg, args.next())?;
+                options.env_rotation = match spec.parse::<f32>() {
+                    Ok(longitude) => Vec3::new(0.0, longitude, 0.0),
+                    Err(_) => parse_vec3(&arg, Some(spec))?,
+                };
+            }
+            "--env-intensity" => options.env_intensity = parse_value(&arg, args.next())?,
+            "--env-tint" => options.env_tint = parse_vec3(&arg, args.next())?,
+            "--background" => {
+                let spec = value(&arg, args.next())?;
+                options.background = Some(match parse_background(&arg, &spec)? {
+                    Background::Map(_) | Background::Blurred(_) | Background::Sky => {
+                        return Err(format!(
+                            "invalid background '{}', expected none, gradient or R,G,B",
+                            spec
+                        ))
+                    }
+                    background => background,
+                });
+            }
+            "--sky" => options.sky = true,
+            "--sun-elevation" => {
+                options.sky_settings.sun_elevation = parse_value(&arg, args.next())?
+            }
+            "--sun-azimuth" => options.sky_settings.sun_azimuth = parse_value(&arg, args.next())?,
+            "--turbidity" => options.sky_settings.turbidity = parse_value(&arg, args.next())?,
+            "--ground-albedo" => {
+                let spec = value(&arg, args.next())?;
+                options.sky_settings.ground_albedo = match spec.parse::<f32>() {
+                    Ok(albedo) => Vec3::new(albedo, albedo, albedo),
+                    Err(_) => parse_vec3(&arg, Some(spec))?,
+                };
+            }
+            "--camera-env" => {
+                options.camera_env = Some(parse_background(&arg, &value(&arg, args.next())?)?)
+            }
+            "--specular-env" => {
+                options.specular_env = Some(parse_background(&arg, &value(&arg, args.next())?)?)
+            }
+            "--exposure" => options.exposure = parse_value(&arg, args.next())?,
+            "--tonemap" => {
+                let name = value(&arg, args.next())?;
+                options.tonemap = ToneMapper::from_name(&name).ok_or_else(|| {
+                    format!(
+                        "unknown tone mapper '{}', expected one of: {}",
+                        name,
+                        ToneMapper::NAMES.join(", ")
+                    )
+                })?;
+            }
+            "--white-point" => options.white_point = parse_value(&arg, args.next())?,
+            "-o" | "--output" => options.output = value(&arg, args.next())?,
+            "--aovs" => options.aovs = true,
+            "--denoise" => options.denoise = true,
+            _ if arg.starts_with('-') => return Err(format!("unknown option '{}'", arg)),
+            _ if !scene_set => {
+                options.scene = arg;
+                scene_set = true;
+            }
+            _ => return Err(format!("unexpected argument '{}'", arg)),
+        }
+    }
+    if !SCENE_NAMES.contains(&options.scene.as_str()) {
+        return Err(format!(
+            "unknown scene '{}', expected one of: {}",
+            options.scene,
+            SCENE_NAMES.join(", ")
+        ));
+    }
+    if [
+        options.environment.is_some(),
+        options.background.is_some(),
+        options.sky,
+    ]
+    .iter()
+    .filter(|set| **set)
+    .count()
+        > 1
+    {
+        return Err(String::from(
+            "only one of --env, --background and --sky can be given",
+        ));
+    }
+    let sky = &options.sky_settings;
+    if !(0.0..=90.0).contains(&sky.sun_elevation) {
+        return Err(String::from(
+            "sun elevation must be between 0 and 90 degrees",
+        ));
+    }
+    if !(1.7..=10.0).contains(&sky.turbidity) {
+        return Err(String::from("turbidity must be between 1.7 and 10"));
+    }
+    let blurred = |spec: &Option<Background>| matches!(spec, Some(Background::Blurred(_)));
+    if options.environment.is_none()
+        && (blurred(&options.camera_env) || blurred(&options.specular_env))
+    {
+        return Err(String::from("blur:<DEGREES> needs an --env map to blur"));
+    }
+    if options
+        .filter_radius
+        .is_some_and(|r| r.is_nan() || r <= 0.0)
+    {
+        return Err(String::from("filter radius must be greater than zero"));
+    }
+    if options.width == 0 || options.height == 0 || options.samples == 0 || options.tile_size == 0 {
+        return Err(String::from(
+            "width, height, spp and tile size must be greater than zero",
+        ));
+    }
+    if options.target_error.is_some_and(|e| e.is_nan() || e <= 0.0) {
+        return Err(String::from("target error must be greater than zero"));
+    }
+    if options
+        .indirect_clamp
+        .is_some_and(|l| l.is_nan() || l <= 0.0)
+    {
+        return Err(String::from("indirect clamp must be greater than zero"));
+    }
+    if options.ao_samples == 0 || options.ao_distance.is_nan() || options.ao_distance <= 0.0 {
+        return Err(String::from(
+            "ao samples and ao distance must be greater than zero",
+        ));
+    }
+    if options.median_of_means == Some(0) {
+        return Err(String::from("median-of-means needs at least one bucket"));
+    }
+    if options.pass_samples == Some(0) || options.save_every == 0 {
+        return Err(String::from(
+            "pass-spp and save-every must be greater than zero",
+        ));
+    }
+    if options.max_samples.is_some_and(|max| max < options.samples) {
+        return Err(String::from("max-spp must be at least spp"));
+    }
+    Ok(Command::Render(Box::new(options)))
+}
+
+fn value(flag: &str, value: Option<String>) -> Result<String, String> {
+    value.ok_or_else(|| format!("missing value for '{}'", flag))
+}
+
+fn parse_value<T: FromStr>(flag: &str, v: Option<String>) -> Result<T, String> {
+    let v = value(flag, v)?;
+    v.parse()
+        .map_err(|_| format!("invalid value '{}' for '{}'", v, flag))
+}
+
+// Anything that is not a keyword, a color or a blur is taken to be a map path.
+fn parse_background(flag: &str, spec: &str) -> Result<Background, String> {
+    Ok(match spec {
+        "none" => Background::None,
+        "gradient" => Background::Gradient,
+        "sky" => Background::Sky,
+        _ if spec.starts_with("blur:") => {
+            Background::Blurred(parse_value(flag, Some(spec["blur:".len()..].to_string()))?)
+        }
+        _ => match parse_vec3(flag, Some(spec.to_string())) {
+            Ok(color) => Background::Constant(color),
+            Err(_) => Background::Map(spec.to_string()),
+        },
+    })
+}
+
+fn parse_vec3(flag: &str, v: Option<String>) -> Result<Vec3, String> {
+    let v = value(flag, v)?;
+    let parts: Vec<f32> = v
+        .split(',')
+        .map(|p| p.trim().parse())
+        .collect::<Result<_, _>>()
+        .map_err(|_| format!("invalid vector '{}' for '{}', expected X,Y,Z", v, flag))?;
+    if parts.len() != 3 {
+        return Err(format!(
+            "invalid vector '{}' for '{}', expected X,Y,Z",
+            v, flag
+        ));
+    }
+    Ok(Vec3::new(parts[0], parts[1], parts[2]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(args: &[&str]) -> Result<Command, String> {
+        parse(args.iter().map(|a| a.to_string()))
+    }
+
+    fn render_options(args: &[&str]) -> Options {
+        match run(args) {
+            Ok(Command::Render(options)) => *options,
+            Ok(_) => panic!("{:?} did not ask for a render", args),
+            Err(err) => panic!("{:?}: {}", args, err),
+        }
+    }
+
+    fn error(args: &[&str]) -> String {
+        match run(args) {
+            Err(err) => err,
+            Ok(_) => panic!("{:?} was accepted", args),
+        }
+    }
+
+    #[test]
+    fn short_h_asks_for_help() {
+        assert!(matches!(run(&["-h"]), Ok(Command::Help)));
+        assert!(matches!(run(&["--help"]), Ok(Command::Help)));
+        let options = render_options(&["cornell_box", "-w", "320", "-H", "240"]);
+        assert_eq!((options.width, options.height), (320, 240));
+        assert_eq!(options.scene, "cornell_box");
+        assert_eq!(render_options(&["--height", "64"]).height, 64);
+    }
+
+    #[test]
+    fn missing_values_are_reported() {
+        assert_eq!(error(&["--spp"]), "missing value for '--spp'");
+        assert_eq!(error(&["-w", "100", "-H"]), "missing value for '-H'");
+        assert_eq!(error(&["--sampler"]), "missing value for '--sampler'");
+    }
+
+    #[test]
+    fn non_numeric_values_are_reported() {
+        assert_eq!(error(&["-n", "ten"]), "invalid value 'ten' for '-n'");
+        assert_eq!(
+            error(&["--height", "-5"]),
+            "invalid value '-5' for '--height'"
+        );
+        assert_eq!(
+            error(&["--look-at", "1,2"]),
+            "invalid vector '1,2' for '--look-at', expected X,Y,Z"
+        );
+    }
+
+    #[test]
+    fn unknown_flags_and_names_are_reported() {
+        assert_eq!(error(&["--frobnicate"]), "unknown option '--frobnicate'");
+        assert_eq!(error(&["-x", "1"]), "unknown option '-x'");
+        assert!(error(&["no_such_scene"]).starts_with("unknown scene 'no_such_scene'"));
+        assert!(error(&["--filter", "blurry"]).starts_with("unknown filter 'blurry'"));
+        assert_eq!(
+            error(&["cornell_box", "extra"]),
+            "unexpected argument 'extra'"
+        );
+    }
+}
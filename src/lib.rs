@@ -0,0 +1,4 @@
+pub mod raytrace;
+pub mod scenes;
+
+pub use raytrace::renderer::{RenderSettings, Renderer};
@@ -1,131 +1,310 @@
-pub mod raytrace;
-pub mod scenes;
+mod cli;
 
-use raytrace::ray::Ray;
-use raytrace::vec::Vec3;
+use raytracing_with_rust::raytrace::adaptive::AdaptiveSettings;
+use raytracing_with_rust::raytrace::aov::Aov;
+use raytracing_with_rust::raytrace::camera::Camera;
+use raytracing_with_rust::raytrace::checkpoint;
+use raytracing_with_rust::raytrace::denoise::DenoiseSettings;
+use raytracing_with_rust::raytrace::environment::{
+    ConstantEnvironment, Environment, Environments, GradientEnvironment, NoEnvironment,
+};
+use raytracing_with_rust::raytrace::integrator::AoSettings;
+use raytracing_with_rust::raytrace::output;
+use raytracing_with_rust::raytrace::sky::SunSky;
+use raytracing_with_rust::raytrace::skymap::IBLSkyMap;
+use raytracing_with_rust::raytrace::tonemap::ToneMapping;
+use raytracing_with_rust::raytrace::vec::Vec3;
+use raytracing_with_rust::scenes;
+use raytracing_with_rust::{RenderSettings, Renderer};
 
+use cli::{Background, Command, Options, USAGE};
 use indicatif::{ProgressBar, ProgressStyle};
-use rayon::prelude::*;
-use raytrace::camera::Camera;
-use raytrace::hitable::Hitable;
-use raytrace::hitable_list::HitableList;
-use raytrace::skymap::radiance;
-use raytrace::skymap::IBLSkyMap;
-use raytrace::vec::drand48;
-
-use scenes::{
-    cornell_box::{cornell_box, cornell_smoke},
-    final_scene::final_scene,
-    lighted_perlin_spheres::lightted_perlin_spheres,
-    // checkered_texture::checkered_texture_scene,
-    // default_scene::default_scene,
-    perlin_spheres::perlin_spheres,
-    random_spheres::random_scene,
-    textured_sphere::textured_spheres,
-    triangle_scene::triangle_scene,
-};
 
-fn color(r: Ray, world: &HitableList, depth: i32, map: &IBLSkyMap) -> Vec3 {
-    match world.hit(r, 0.001, std::f32::MAX) {
-        Some(rec) => {
-            if depth >= 50 {
-                return Vec3::new(0.0, 0.0, 0.0);
-            }
-            let (mut emitted, lpos) = rec.material.emitted(rec.u, rec.v, rec.p);
-            let surface_normal = -rec.normal;
-            let direction_to_light = (lpos - rec.p).normalize();
-            let shadow_ray = Ray::new(rec.p + (surface_normal * 0.001), direction_to_light, 0.0);
-            match world.hit(shadow_ray, 0.001, std::f32::MAX) {
-                Some(_l) => {
-                    emitted = emitted;
-                }
-                None => {
-                    emitted = Vec3::new(0.0, 0.0, 0.0);
-                }
-            }
-            if let Some((scattered, attenuation)) = rec.material.scatter(&r, &rec) {
-                emitted + attenuation * color(scattered, world, depth + 1, map)
-            } else {
-                emitted
+use std::io;
+use std::path::Path;
+use std::process;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+fn main() {
+    let options = match cli::parse(std::env::args().skip(1)) {
+        Ok(Command::Render(options)) => options,
+        Ok(Command::ListScenes) => {
+            for name in scenes::SCENE_NAMES {
+                println!("{}", name);
             }
+            return;
+        }
+        Ok(Command::Help) => {
+            print!("{}", USAGE);
+            return;
         }
-        None => {
-            radiance(map, r)
-            // Vec3::new(0.0, 0.0, 0.0)
-            // let unit_direction = Vec3::unit_vector(r.direction());
-            // let t = 0.5 * (unit_direction.y() + 1.0);
-            // return (1.0 - t) * Vec3::new(1.0, 1.0, 1.0) + t * Vec3::new(0.5, 0.7, 1.0);
+        Err(err) => {
+            eprintln!("error: {}\n\n{}", err, USAGE);
+            process::exit(2);
         }
+    };
+    if let Err(err) = render(&options) {
+        eprintln!("error: {}", err);
+        process::exit(1);
     }
 }
 
-fn main() {
-    let nx = 500;
-    let ny = 500;
-    let ns = 10;
+fn render(options: &Options) -> io::Result<()> {
+    output::ImageFormat::from_path(Path::new(&options.output))?;
 
-    let progress_bar = ProgressBar::new((nx as usize * ny as usize / 64) as u64);
-    progress_bar.set_prefix("Tracing some rays");
-    progress_bar.set_style(
-        ProgressStyle::default_bar()
-            .template("{prefix:.white} [{elapsed_precise}] {bar:40.cyan/blue} {percent}%"),
-    );
+    let settings = RenderSettings {
+        width: options.width,
+        height: options.height,
+        samples: options.samples,
+        max_depth: options.max_depth,
+        rr_min_depth: options.rr_min_depth,
+        seed: options.seed,
+        sampler: options.sampler,
+        filter: options.filter,
+        filter_radius: options.filter_radius,
+        aovs: options.aovs,
+        adaptive: options.max_samples.map(|max_samples| AdaptiveSettings {
+            max_samples,
+            threshold: options.error_threshold,
+            ..AdaptiveSettings::default()
+        }),
+        denoise: if options.denoise {
+            Some(DenoiseSettings::default())
+        } else {
+            None
+        },
+        indirect_clamp: options.indirect_clamp,
+        median_of_means: options.median_of_means,
+        integrator: options.integrator,
+        ao: AoSettings {
+            samples: options.ao_samples,
+            max_distance: options.ao_distance,
+        },
+        tile_size: options.tile_size,
+        tile_order: options.tile_order,
+    };
 
-    print!("P3\n{} {}\n255\n", nx, ny);
-    let look_from: Vec3 = Vec3::new(278.0, 278.0, -800.0);
-    let look_at: Vec3 = Vec3::new(278.0, 278.0, 0.0);
-    let dist_to_focus = 10.0;
-    let aperature: f32 = 0.0;
+    let scene =
+        scenes::load(&options.scene, settings.seed).expect("scene name is validated by the cli");
+    let look_from = options.look_from.unwrap_or(scene.look_from);
+    let look_at = options.look_at.unwrap_or(scene.look_at);
 
     let cam = Camera::new(
         look_from,
         look_at,
         Vec3::new(0.0, 1.0, 0.0),
-        40.0,
-        nx as f32 / ny as f32,
-        aperature,
-        dist_to_focus,
+        options.vfov.unwrap_or(scene.vfov),
+        settings.width as f32 / settings.height as f32,
+        options.aperture.unwrap_or(scene.aperture),
+        options.focus_dist.unwrap_or(scene.focus_dist),
         0.0,
         1.0,
     );
 
-    let world = random_scene();
-    let map = IBLSkyMap::new("assets/sunrise.hdr", 3.3);
-    let rows: Vec<Vec<Vec3>> = (0..ny)
-        .into_par_iter()
-        .rev()
-        .map(|j| {
-            (0..nx)
-                .into_par_iter()
-                .map(|i| {
-                    let mut col = Vec3::new(0.0, 0.0, 0.0);
-                    for _s in 0..ns {
-                        let u = (i as f32 + drand48()) / nx as f32;
-                        let v = (j as f32 + drand48()) / ny as f32;
-                        let r = cam.get_ray(u, v);
-                        col += color(r, &world, 0, &map);
-                    }
-                    if i % 64 == 0 {
-                        progress_bar.inc(1);
-                    }
-                    col /= ns as f32;
-                    col = Vec3::new(f32::sqrt(col[0]), f32::sqrt(col[1]), f32::sqrt(col[2]));
-                    col *= 255.99;
-                    col
-                })
-                .collect()
-        })
-        .collect();
+    let map = match &options.environment {
+        Some(path) => Some(Arc::new(load_map(path, options)?)),
+        None => None,
+    };
+    let environment: Arc<dyn Environment> = match (&map, &options.background) {
+        (Some(map), _) => map.clone(),
+        (None, Some(background)) => background_environment(background, None, options)?,
+        (None, None) if options.sky => Arc::new(sun_sky(options)),
+        (None, None) => scene.environment,
+    };
+    // Camera and specular rays may see something else than the light the scene receives.
+    let mut environments = Environments::new(environment);
+    if let Some(background) = &options.camera_env {
+        environments =
+            environments.with_camera(background_environment(background, map.as_deref(), options)?);
+    }
+    if let Some(background) = &options.specular_env {
+        environments = environments.with_specular(background_environment(
+            background,
+            map.as_deref(),
+            options,
+        )?);
+    }
+    let renderer = Renderer::new(scene.world, cam, environments, settings);
+
+    let tone_mapping = ToneMapping {
+        exposure: options.exposure,
+        operator: options.tonemap,
+        white_point: options.white_point,
+    };
+    let path = Path::new(&options.output);
+
+    // Each pass brings every pixel `pass` samples closer to the goal; a checkpoint from an
+    // earlier run is continued from where it stopped.
+    let fingerprint = checkpoint::fingerprint(
+        &options.scene,
+        &scene_inputs(options),
+        &settings,
+        renderer.records_aovs(),
+    );
+    let mut state = renderer.new_state();
+    if let Some(checkpoint_path) = &options.checkpoint {
+        let checkpoint_path = Path::new(checkpoint_path);
+        if checkpoint_path.exists() {
+            checkpoint::load(checkpoint_path, &mut state, &fingerprint)?;
+            println!("Resuming from {} spp", state.target);
+        }
+    }
+    // A time or noise budget keeps adding passes until it is met; adaptive sampling still stops
+    // at its maximum.
+    let bounded = options.time_limit.is_some() || options.target_error.is_some();
+    let goal = match (bounded, &settings.adaptive) {
+        (true, None) => usize::MAX,
+        _ => renderer.target_samples(),
+    };
+    let pass = options
+        .pass_samples
+        .unwrap_or(if bounded { settings.samples } else { goal });
+    let passes = goal.saturating_sub(state.target).div_ceil(pass);
+    let pixels = (settings.width * settings.height) as u64;
+
+    // Progress counts pixel passes, bumped as each tile finishes. Bounded renders do not know
+    // their pass count, so the bar grows by a pass at a time.
+    let progress_bar = ProgressBar::new(if bounded {
+        pixels
+    } else {
+        pixels * passes as u64
+    });
+    progress_bar.set_prefix("Tracing some rays");
+    progress_bar.set_style(
+        ProgressStyle::default_bar().template(
+            "{prefix:.white} [{elapsed_precise}] {bar:40.cyan/blue} {percent}% (eta {eta})",
+        ),
+    );
+    let rays = AtomicU64::new(0);
+    let start = Instant::now();
+    let mut last_pass = Duration::default();
+    for index in 1.. {
+        if state.target >= goal {
+            break;
+        }
+        // Stop before a pass as long as the last one would overrun the time limit.
+        let out_of_time = options
+            .time_limit
+            .is_some_and(|limit| start.elapsed() + last_pass > limit);
+        let converged = options
+            .target_error
+            .is_some_and(|target| state.error() <= target);
+        if state.target > 0 && (out_of_time || converged) {
+            break;
+        }
+        if bounded && index > 1 {
+            progress_bar.inc_length(pixels);
+        }
+        let pass_start = Instant::now();
+        let target = state.target.saturating_add(pass).min(goal);
+        renderer.render_pass(&mut state, target, |tile| {
+            rays.fetch_add(tile.rays, Ordering::Relaxed);
+            progress_bar.inc(tile.pixels as u64);
+        });
+        last_pass = pass_start.elapsed();
+        if index % options.save_every == 0 && state.target < goal {
+            output::save(path, &state.beauty.to_rows(), &tone_mapping)?;
+            if let Some(checkpoint_path) = &options.checkpoint {
+                checkpoint::save(Path::new(checkpoint_path), &state, &fingerprint)?;
+            }
+        }
+    }
     progress_bar.finish_with_message("finished");
+    let seconds = start.elapsed().as_secs_f64();
+    let rays = rays.into_inner();
+    if rays > 0 {
+        println!(
+            "Traced {} rays in {:.1}s ({:.2} Mrays/s)",
+            rays,
+            seconds,
+            rays as f64 / seconds.max(1e-9) / 1e6
+        );
+    }
+    if bounded {
+        println!(
+            "Reached {:.1} spp on average with an estimated relative error of {:.4}",
+            state.mean_samples(),
+            state.error()
+        );
+    }
 
-    for r in rows {
-        for col in r {
-            print!(
-                "{} {} {}\n",
-                col.r().min(255.0) as i32,
-                col.g().min(255.0) as i32,
-                col.b().min(255.0) as i32
-            );
+    if let Some(checkpoint_path) = &options.checkpoint {
+        checkpoint::save(Path::new(checkpoint_path), &state, &fingerprint)?;
+    }
+    let layers = renderer.finish(state);
+    output::save(path, &layers.image(), &tone_mapping)?;
+    if let Some(aovs) = &layers.aovs {
+        for aov in Aov::ALL.iter() {
+            let rows = aovs.film(*aov).to_rows();
+            output::save(&output::aov_path(path, aov.name()), &rows, &tone_mapping)?;
         }
     }
+    if options.sample_heatmap {
+        let counts: Vec<Vec<f32>> = layers
+            .sample_counts
+            .iter()
+            .map(|row| row.iter().map(|c| *c as f32).collect())
+            .collect();
+        output::save(
+            &output::pass_path(path, "samples", "png"),
+            &output::heatmap(&counts),
+            &ToneMapping::default(),
+        )?;
+    }
+    Ok(())
+}
+
+// The camera and environment options a checkpoint's samples depend on.
+fn scene_inputs(options: &Options) -> String {
+    format!(
+        "look_from={:?} look_at={:?} vfov={:?} aperture={:?} focus_dist={:?} env={:?} \
+         env_rotation={:?} env_intensity={:?} env_tint={:?} background={:?} camera_env={:?} \
+         specular_env={:?} sky={} sky_settings={:?}",
+        options.look_from,
+        options.look_at,
+        options.vfov,
+        options.aperture,
+        options.focus_dist,
+        options.environment,
+        options.env_rotation,
+        options.env_intensity,
+        options.env_tint,
+        options.background,
+        options.camera_env,
+        options.specular_env,
+        options.sky,
+        options.sky_settings
+    )
+}
+
+// Environment maps from the command line share its rotation, intensity and tint.
+fn load_map(path: &str, options: &Options) -> io::Result<IBLSkyMap> {
+    Ok(IBLSkyMap::new(path)?
+        .with_euler_rotation(options.env_rotation)
+        .with_scale(options.env_intensity * options.env_tint))
+}
+
+fn sun_sky(options: &Options) -> SunSky {
+    SunSky::new(options.sky_settings).with_scale(options.env_intensity * options.env_tint)
+}
+
+// `map` is the --env map, which a blurred background is made from.
+fn background_environment(
+    background: &Background,
+    map: Option<&IBLSkyMap>,
+    options: &Options,
+) -> io::Result<Arc<dyn Environment>> {
+    Ok(match background {
+        Background::None => Arc::new(NoEnvironment),
+        Background::Gradient => Arc::new(GradientEnvironment::default()),
+        Background::Constant(color) => Arc::new(ConstantEnvironment::new(*color)),
+        Background::Map(path) => Arc::new(load_map(path, options)?),
+        Background::Blurred(degrees) => Arc::new(
+            map.expect("blurred backgrounds are validated by the cli")
+                .blurred(*degrees),
+        ),
+        Background::Sky => Arc::new(sun_sky(options)),
+    })
 }
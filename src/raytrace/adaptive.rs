@@ -0,0 +1,87 @@
+// Adaptive sampling: after the first `RenderSettings::samples`, pixels get further batches
+// until their estimated error drops below `threshold` or they reach `max_samples`.
+#[derive(Clone, Copy, Debug)]
+pub struct AdaptiveSettings {
+    pub max_samples: usize,
+    pub batch_size: usize,
+    // Relative standard error of the pixel mean at which sampling stops.
+    pub threshold: f32,
+}
+
+impl Default for AdaptiveSettings {
+    fn default() -> Self {
+        AdaptiveSettings {
+            max_samples: 256,
+            batch_size: 8,
+            threshold: 0.05,
+        }
+    }
+}
+
+// Running mean and variance of the sample luminances of one pixel (Welford's algorithm).
+#[derive(Clone, Copy, Debug, Default)]
+pub struct PixelStats {
+    pub count: usize,
+    pub mean: f32,
+    // Sum of squared differences from the mean.
+    pub m2: f32,
+}
+
+impl PixelStats {
+    pub fn add(&mut self, value: f32) {
+        self.count += 1;
+        let delta = value - self.mean;
+        self.mean += delta / self.count as f32;
+        self.m2 += delta * (value - self.mean);
+    }
+
+    pub fn variance(&self) -> f32 {
+        if self.count < 2 {
+            return 0.0;
+        }
+        self.m2 / (self.count - 1) as f32
+    }
+
+    // Standard error of the mean relative to the mean. The small floor keeps dark pixels
+    // from asking for samples without end.
+    pub fn relative_error(&self) -> f32 {
+        if self.count < 2 {
+            return f32::INFINITY;
+        }
+        (self.variance() / self.count as f32).sqrt() / (self.mean + 0.01)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn running_statistics_match_the_samples() {
+        let values = [0.5, 2.0, 1.25, 0.0, 3.5, 0.75];
+        let mut stats = PixelStats::default();
+        for v in values.iter() {
+            stats.add(*v);
+        }
+        let n = values.len() as f32;
+        let mean = values.iter().sum::<f32>() / n;
+        let variance = values.iter().map(|v| (v - mean) * (v - mean)).sum::<f32>() / (n - 1.0);
+        assert_eq!(stats.count, values.len());
+        assert!((stats.mean - mean).abs() < 1e-6);
+        assert!((stats.variance() - variance).abs() < 1e-5);
+        let error = (variance / n).sqrt() / (mean + 0.01);
+        assert!((stats.relative_error() - error).abs() < 1e-5);
+    }
+
+    #[test]
+    fn error_is_unknown_below_two_samples_and_zero_for_constant_pixels() {
+        let mut stats = PixelStats::default();
+        assert_eq!(stats.relative_error(), f32::INFINITY);
+        stats.add(0.4);
+        assert_eq!(stats.relative_error(), f32::INFINITY);
+        for _ in 0..5 {
+            stats.add(0.4);
+        }
+        assert_eq!(stats.relative_error(), 0.0);
+    }
+}
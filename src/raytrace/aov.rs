@@ -0,0 +1,185 @@
+use super::film::Film;
+use super::filter::BoxFilter;
+use super::vec::Vec3;
+
+// First-hit buffers written next to the beauty pass.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Aov {
+    Albedo,
+    Normal,
+    Position,
+    Depth,
+    Uv,
+    ObjectId,
+}
+
+impl Aov {
+    pub const ALL: [Aov; 6] = [
+        Aov::Albedo,
+        Aov::Normal,
+        Aov::Position,
+        Aov::Depth,
+        Aov::Uv,
+        Aov::ObjectId,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Aov::Albedo => "albedo",
+            Aov::Normal => "normal",
+            Aov::Position => "position",
+            Aov::Depth => "depth",
+            Aov::Uv => "uv",
+            Aov::ObjectId => "object_id",
+        }
+    }
+}
+
+// What a camera ray saw at its first hit. Rays that escape keep the zero defaults.
+#[derive(Clone, Copy, Debug)]
+pub struct AovSample {
+    pub albedo: Vec3,
+    // World space shading normal.
+    pub normal: Vec3,
+    pub position: Vec3,
+    // Distance along the camera's viewing direction.
+    pub depth: f32,
+    pub uv: (f32, f32),
+    // One plus the index of the top-level object in the world, zero for the background.
+    pub object_id: u32,
+}
+
+impl Default for AovSample {
+    fn default() -> Self {
+        let zero = Vec3::new(0.0, 0.0, 0.0);
+        AovSample {
+            albedo: zero,
+            normal: zero,
+            position: zero,
+            depth: 0.0,
+            uv: (0.0, 0.0),
+            object_id: 0,
+        }
+    }
+}
+
+impl AovSample {
+    // Scalars are repeated across the three channels.
+    pub fn value(&self, aov: Aov) -> Vec3 {
+        match aov {
+            Aov::Albedo => self.albedo,
+            Aov::Normal => self.normal,
+            Aov::Position => self.position,
+            Aov::Depth => Vec3::new(self.depth, self.depth, self.depth),
+            Aov::Uv => Vec3::new(self.uv.0, self.uv.1, 0.0),
+            Aov::ObjectId => {
+                let id = self.object_id as f32;
+                Vec3::new(id, id, id)
+            }
+        }
+    }
+}
+
+// One film per AOV. Samples are averaged within their pixel rather than spread by the
+// reconstruction filter, and object IDs keep only the first sample so they stay exact.
+#[derive(Clone)]
+pub struct AovFilms {
+    films: Vec<Film>,
+}
+
+impl AovFilms {
+    pub fn new(width: usize, height: usize) -> Self {
+        AovFilms::with_bounds(width, height, 0, 0, width, height)
+    }
+
+    pub fn with_bounds(
+        width: usize,
+        height: usize,
+        x0: usize,
+        y0: usize,
+        x1: usize,
+        y1: usize,
+    ) -> Self {
+        AovFilms {
+            films: Aov::ALL
+                .iter()
+                .map(|_| Film::with_bounds(width, height, x0, y0, x1, y1))
+                .collect(),
+        }
+    }
+
+    // Adds sample number `index` of raster pixel (x, y).
+    pub fn add_sample(&mut self, x: usize, y: usize, index: usize, sample: &AovSample) {
+        let filter = BoxFilter { radius: 0.5 };
+        for (aov, film) in Aov::ALL.iter().zip(self.films.iter_mut()) {
+            if *aov == Aov::ObjectId && index > 0 {
+                continue;
+            }
+            film.add_sample(x as f32 + 0.5, y as f32 + 0.5, sample.value(*aov), &filter);
+        }
+    }
+
+    pub fn merge(&mut self, other: &AovFilms) {
+        for (film, other) in self.films.iter_mut().zip(other.films.iter()) {
+            film.merge(other);
+        }
+    }
+
+    // All films, in the order of `Aov::ALL`.
+    pub fn films(&self) -> &[Film] {
+        &self.films
+    }
+
+    pub fn films_mut(&mut self) -> &mut [Film] {
+        &mut self.films
+    }
+
+    pub fn film(&self, aov: Aov) -> &Film {
+        let index = Aov::ALL.iter().position(|a| *a == aov).unwrap();
+        &self.films[index]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(value: f32, object_id: u32) -> AovSample {
+        AovSample {
+            albedo: Vec3::new(value, value, value),
+            depth: value,
+            uv: (value, 1.0 - value),
+            object_id,
+            ..AovSample::default()
+        }
+    }
+
+    #[test]
+    fn samples_average_within_their_pixel_and_ids_stay_exact() {
+        let mut films = AovFilms::new(2, 2);
+        films.add_sample(1, 0, 0, &sample(0.25, 3));
+        films.add_sample(1, 0, 1, &sample(0.75, 7));
+        assert_eq!(films.film(Aov::Albedo).value(1, 0).g(), 0.5);
+        assert_eq!(films.film(Aov::Depth).value(1, 0).r(), 0.5);
+        assert_eq!(films.film(Aov::Uv).value(1, 0).g(), 0.5);
+        assert_eq!(films.film(Aov::ObjectId).value(1, 0).r(), 3.0);
+        for aov in Aov::ALL.iter() {
+            assert_eq!(films.film(*aov).pixel(0, 0).weight, 0.0, "{}", aov.name());
+        }
+    }
+
+    #[test]
+    fn merged_tiles_keep_their_own_pixels() {
+        let mut films = AovFilms::new(2, 1);
+        let mut left = AovFilms::with_bounds(2, 1, 0, 0, 1, 1);
+        let mut right = AovFilms::with_bounds(2, 1, 1, 0, 2, 1);
+        left.add_sample(0, 0, 0, &sample(0.2, 1));
+        right.add_sample(1, 0, 0, &sample(0.6, 2));
+        films.merge(&left);
+        films.merge(&right);
+        let ids = films.film(Aov::ObjectId);
+        assert_eq!((ids.value(0, 0).r(), ids.value(1, 0).r()), (1.0, 2.0));
+        let depth = films.film(Aov::Depth);
+        assert_eq!((depth.value(0, 0).r(), depth.value(1, 0).r()), (0.2, 0.6));
+    }
+}
@@ -1,8 +1,12 @@
 use super::aabb::AABB;
-use super::hitable::{HitRecord, Hitable};
+use super::hitable::{HitRecord, Hitable, TraversalStats};
+use super::light::Light;
+use super::material::Material;
 use super::ray::Ray;
 
-use rand::Rng;
+use super::rng::Rng;
+
+use std::sync::Arc;
 
 pub struct BVHNode {
     left: Box<dyn Hitable>,
@@ -14,8 +18,13 @@ impl BVHNode {
     pub fn new(bbox: AABB, left: Box<dyn Hitable>, right: Box<dyn Hitable>) -> Self {
         BVHNode { left, right, bbox }
     }
-    pub fn construct(mut hitable_list: Vec<Box<dyn Hitable>>, t0: f32, t1: f32) -> Box<dyn Hitable> {
-        let axis = rand::thread_rng().gen_range(0, 3);
+    pub fn construct(
+        mut hitable_list: Vec<Box<dyn Hitable>>,
+        t0: f32,
+        t1: f32,
+        rng: &mut Rng,
+    ) -> Box<dyn Hitable> {
+        let axis = rng.gen_range(3);
         hitable_list.sort_by(|a, b| {
             let left_hit = a.required_bounding_box(0.0, 0.0).min;
             let right_hit = b.required_bounding_box(0.0, 0.0).min;
@@ -36,8 +45,8 @@ impl BVHNode {
             _ => {
                 let mut a = hitable_list;
                 let b = a.split_off(a.len() / 2);
-                let left = Self::construct(b, t0, t1);
-                let right = Self::construct(a, t0, t1);
+                let left = Self::construct(b, t0, t1, rng);
+                let right = Self::construct(a, t0, t1, rng);
                 let bbox = left
                     .required_bounding_box(t0, t1)
                     .surrounding_box(&right.required_bounding_box(t0, t1));
@@ -52,12 +61,33 @@ impl Hitable for BVHNode {
         Some(self.bbox)
     }
 
+    fn collect_lights(&self, lights: &mut Vec<Arc<dyn Light>>) {
+        self.left.collect_lights(lights);
+        self.right.collect_lights(lights);
+    }
+
+    fn collect_materials(&self, materials: &mut Vec<Arc<dyn Material>>) {
+        self.left.collect_materials(materials);
+        self.right.collect_materials(materials);
+    }
+
     fn hit(&self, r: Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+        self.hit_counted(r, t_min, t_max, &mut TraversalStats::default())
+    }
+
+    fn hit_counted(
+        &self,
+        r: Ray,
+        t_min: f32,
+        t_max: f32,
+        stats: &mut TraversalStats,
+    ) -> Option<HitRecord> {
+        stats.aabb_tests += 1;
         match self.bbox.hit(r, t_min, t_max) {
             false => None,
             true => {
-                let hit_left = self.left.hit(r, t_min, t_max);
-                let hit_right = self.right.hit(r, t_min, t_max);
+                let hit_left = self.left.hit_counted(r, t_min, t_max, stats);
+                let hit_right = self.right.hit_counted(r, t_min, t_max, stats);
                 match (hit_left, hit_right) {
                     (None, None) => None,
                     (None, Some(hit_record)) => Some(hit_record),
@@ -1,5 +1,5 @@
 use super::ray::Ray;
-use super::vec::{drand48, random_in_unit_sphere, Vec3};
+use super::vec::{random_in_unit_disk, Vec3};
 
 use std::f32::consts::PI;
 
@@ -16,6 +16,7 @@ pub struct Camera {
     lens_radius: f32,
 }
 impl Camera {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         look_from: Vec3,
         look_at: Vec3,
@@ -37,7 +38,8 @@ impl Camera {
         let w = Vec3::unit_vector(look_from - look_at);
         let u = Vec3::unit_vector(Vec3::cross(vup, w));
         let v = Vec3::cross(w, u);
-        let lower_left_corner = origin - half_width * focus_dist * u - half_height * focus_dist * v - focus_dist * w;
+        let lower_left_corner =
+            origin - half_width * focus_dist * u - half_height * focus_dist * v - focus_dist * w;
         let horizontal = 2.0 * half_width * focus_dist * u;
         let vertical = 2.0 * half_height * focus_dist * v;
 
@@ -55,10 +57,16 @@ impl Camera {
         }
     }
 
-    pub fn get_ray(&self, u: f32, v: f32) -> Ray {
-        let rd: Vec3 = self.lens_radius * random_in_unit_sphere();
+    // Distance of `p` in front of the camera, measured along the viewing direction -w.
+    pub fn depth(&self, p: Vec3) -> f32 {
+        -Vec3::dot(&(p - self.origin), &self.w)
+    }
+
+    // `lens` and `time` are uniform samples for the aperture position and shutter time.
+    pub fn get_ray(&self, u: f32, v: f32, lens: (f32, f32), time: f32) -> Ray {
+        let rd: Vec3 = self.lens_radius * random_in_unit_disk(lens);
         let offset = self.u * rd.x() + self.v * rd.y();
-        let time: f32 = self.time0 + drand48() * (self.time1 - self.time0);
+        let time: f32 = self.time0 + time * (self.time1 - self.time0);
         Ray::new(
             self.origin + offset,
             self.lower_left_corner + u * self.horizontal + v * self.vertical - self.origin - offset,
@@ -0,0 +1,273 @@
+use super::film::Film;
+use super::renderer::{RenderSettings, RenderState};
+use super::sampler::SamplerKind;
+use super::vec::Vec3;
+
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+const MAGIC: &[u8; 8] = b"RTCKPT01";
+
+// Describes everything a checkpoint's samples depend on, apart from the sample counts, which
+// a resumed render is free to raise. The stratified sampler is the exception, as its strata
+// are sized for the sample count. `inputs` describes what the settings leave out, such as
+// camera overrides and the environment.
+pub fn fingerprint(
+    scene: &str,
+    inputs: &str,
+    settings: &RenderSettings,
+    records_aovs: bool,
+) -> String {
+    let strata = match settings.sampler {
+        SamplerKind::Stratified => Some(settings.samples),
+        _ => None,
+    };
+    // Where adaptive sampling stops, but not the sample limit.
+    let adaptive = settings
+        .adaptive
+        .map(|adaptive| (adaptive.batch_size, adaptive.threshold));
+    format!(
+        "scene={} inputs=[{}] size={}x{} depth={} rr={} seed={} sampler={:?} filter={:?} \
+         strata={:?} radius={:?} aovs={} clamp={:?} buckets={:?} integrator={:?} ao={:?} \
+         adaptive={:?}",
+        scene,
+        inputs,
+        settings.width,
+        settings.height,
+        settings.max_depth,
+        settings.rr_min_depth,
+        settings.seed,
+        settings.sampler,
+        settings.filter,
+        strata,
+        settings.filter_radius,
+        records_aovs,
+        settings.indirect_clamp,
+        settings.median_of_means,
+        settings.integrator,
+        settings.ao,
+        adaptive
+    )
+}
+
+// Writes the accumulated films and per-pixel statistics, little-endian. The file is written
+// next to `path` first and renamed over it, so an interrupted write keeps the old checkpoint.
+pub fn save(path: &Path, state: &RenderState, fingerprint: &str) -> io::Result<()> {
+    let temp = path.with_extension("tmp");
+    {
+        let mut out = BufWriter::new(File::create(&temp)?);
+        out.write_all(MAGIC)?;
+        write_u64(&mut out, fingerprint.len() as u64)?;
+        out.write_all(fingerprint.as_bytes())?;
+        write_u64(&mut out, state.target as u64)?;
+        write_film(&mut out, &state.beauty)?;
+        if let Some(aovs) = &state.aovs {
+            for film in aovs.films() {
+                write_film(&mut out, film)?;
+            }
+        }
+        for stats in &state.stats {
+            write_u64(&mut out, stats.count as u64)?;
+            write_f32(&mut out, stats.mean)?;
+            write_f32(&mut out, stats.m2)?;
+        }
+        out.flush()?;
+    }
+    fs::rename(&temp, path)
+}
+
+// Reads a checkpoint into `state`, which must come from `Renderer::new_state` for the same
+// settings the checkpoint was written with.
+pub fn load(path: &Path, state: &mut RenderState, fingerprint: &str) -> io::Result<()> {
+    let mut input = BufReader::new(File::open(path)?);
+    let mut magic = [0u8; 8];
+    input.read_exact(&mut magic)?;
+    if &magic != MAGIC {
+        return Err(invalid(path, "not a render checkpoint"));
+    }
+    let len = read_u64(&mut input)? as usize;
+    if len != fingerprint.len() {
+        return Err(invalid(path, "written with different render settings"));
+    }
+    let mut stored = vec![0u8; len];
+    input.read_exact(&mut stored)?;
+    if stored != fingerprint.as_bytes() {
+        return Err(invalid(path, "written with different render settings"));
+    }
+    state.target = read_u64(&mut input)? as usize;
+    read_film(&mut input, &mut state.beauty)?;
+    if let Some(aovs) = &mut state.aovs {
+        for film in aovs.films_mut() {
+            read_film(&mut input, film)?;
+        }
+    }
+    for stats in state.stats.iter_mut() {
+        stats.count = read_u64(&mut input)? as usize;
+        stats.mean = read_f32(&mut input)?;
+        stats.m2 = read_f32(&mut input)?;
+    }
+    Ok(())
+}
+
+fn invalid(path: &Path, message: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("checkpoint '{}': {}", path.display(), message),
+    )
+}
+
+fn write_film<W: Write>(out: &mut W, film: &Film) -> io::Result<()> {
+    for pixel in film.pixels() {
+        write_f32(out, pixel.sum.r())?;
+        write_f32(out, pixel.sum.g())?;
+        write_f32(out, pixel.sum.b())?;
+        write_f32(out, pixel.weight)?;
+    }
+    Ok(())
+}
+
+fn read_film<R: Read>(input: &mut R, film: &mut Film) -> io::Result<()> {
+    for pixel in film.pixels_mut() {
+        let r = read_f32(input)?;
+        let g = read_f32(input)?;
+        let b = read_f32(input)?;
+        pixel.sum = Vec3::new(r, g, b);
+        pixel.weight = read_f32(input)?;
+    }
+    Ok(())
+}
+
+fn write_u64<W: Write>(out: &mut W, v: u64) -> io::Result<()> {
+    out.write_all(&v.to_le_bytes())
+}
+
+fn write_f32<W: Write>(out: &mut W, v: f32) -> io::Result<()> {
+    out.write_all(&v.to_le_bytes())
+}
+
+fn read_u64<R: Read>(input: &mut R) -> io::Result<u64> {
+    let mut bytes = [0u8; 8];
+    input.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+fn read_f32<R: Read>(input: &mut R) -> io::Result<f32> {
+    let mut bytes = [0u8; 4];
+    input.read_exact(&mut bytes)?;
+    Ok(f32::from_le_bytes(bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::adaptive::AdaptiveSettings;
+    use super::super::adaptive::PixelStats;
+    use super::super::aov::AovFilms;
+    use super::super::filter::BoxFilter;
+    use super::*;
+
+    fn state(aovs: bool) -> RenderState {
+        RenderState {
+            beauty: Film::new(3, 2).with_buckets(2),
+            aovs: if aovs {
+                Some(AovFilms::new(3, 2))
+            } else {
+                None
+            },
+            stats: vec![PixelStats::default(); 6],
+            target: 0,
+        }
+    }
+
+    fn rendered() -> RenderState {
+        let mut state = state(true);
+        let filter = BoxFilter { radius: 0.5 };
+        for i in 0..6 {
+            let (x, y) = ((i % 3) as f32 + 0.5, (i / 3) as f32 + 0.5);
+            let value = Vec3::new(i as f32, 0.5, -1.25);
+            state.beauty.add_bucket_sample(i, x, y, value, &filter);
+            state.aovs.as_mut().unwrap().films_mut()[i % 2].add_sample(x, y, value, &filter);
+            state.stats[i].add(i as f32 * 0.3);
+            state.stats[i].add(1.0);
+        }
+        state.target = 8;
+        state
+    }
+
+    fn temp_path(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("checkpoint-{}-{}.ckpt", std::process::id(), name))
+    }
+
+    fn bits<'a>(films: impl IntoIterator<Item = &'a Film>) -> Vec<u32> {
+        films
+            .into_iter()
+            .flat_map(|film| film.pixels())
+            .flat_map(|p| vec![p.sum.r(), p.sum.g(), p.sum.b(), p.weight])
+            .map(f32::to_bits)
+            .collect()
+    }
+
+    #[test]
+    fn save_then_load_restores_the_state() {
+        let path = temp_path("round-trip");
+        let saved = rendered();
+        save(&path, &saved, "fingerprint").unwrap();
+        let mut loaded = state(true);
+        load(&path, &mut loaded, "fingerprint").unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(loaded.target, 8);
+        assert_eq!(bits(Some(&loaded.beauty)), bits(Some(&saved.beauty)));
+        let aovs = |s: &RenderState| bits(s.aovs.as_ref().unwrap().films());
+        assert_eq!(aovs(&loaded), aovs(&saved));
+        for (a, b) in loaded.stats.iter().zip(&saved.stats) {
+            assert_eq!((a.count, a.mean, a.m2), (b.count, b.mean, b.m2));
+        }
+    }
+
+    #[test]
+    fn load_rejects_other_settings_and_truncated_files() {
+        let path = temp_path("rejects");
+        save(&path, &rendered(), "fingerprint").unwrap();
+        assert!(load(&path, &mut state(true), "fingerprint!").is_err());
+        assert!(load(&path, &mut state(true), "fingerprinT").is_err());
+
+        let data = fs::read(&path).unwrap();
+        fs::write(&path, &data[..data.len() - 1]).unwrap();
+        assert!(load(&path, &mut state(true), "fingerprint").is_err());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn fingerprint_allows_more_samples() {
+        let print = |settings: &RenderSettings| fingerprint("scene", "", settings, false);
+        let adaptive = RenderSettings {
+            adaptive: Some(AdaptiveSettings::default()),
+            ..RenderSettings::default()
+        };
+        let more = |settings: &RenderSettings| RenderSettings {
+            samples: settings.samples * 2,
+            adaptive: settings.adaptive.map(|a| AdaptiveSettings {
+                max_samples: a.max_samples * 2,
+                ..a
+            }),
+            ..*settings
+        };
+        assert_eq!(print(&adaptive), print(&more(&adaptive)));
+        let stricter = RenderSettings {
+            adaptive: Some(AdaptiveSettings {
+                threshold: 0.01,
+                ..AdaptiveSettings::default()
+            }),
+            ..adaptive
+        };
+        assert_ne!(print(&adaptive), print(&stricter));
+
+        // Stratified samples are laid out for the sample count.
+        let stratified = RenderSettings {
+            sampler: SamplerKind::Stratified,
+            ..RenderSettings::default()
+        };
+        assert_ne!(print(&stratified), print(&more(&stratified)));
+    }
+}
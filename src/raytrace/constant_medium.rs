@@ -2,10 +2,10 @@ use super::aabb::AABB;
 use super::hitable::{HitRecord, Hitable};
 use super::material::{Isotropic, Material};
 use super::ray::Ray;
+use super::rng::Rng;
 use super::texture::Texture;
-use super::vec::{drand48, Vec3};
+use super::vec::Vec3;
 
-use std::f32::{MAX, MIN};
 use std::sync::Arc;
 
 pub struct ConstantMedium {
@@ -26,8 +26,8 @@ impl ConstantMedium {
 
 impl Hitable for ConstantMedium {
     fn hit(&self, r: Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
-        match self.boundry.hit(r, MIN, MAX) {
-            Some(mut rec1) => match self.boundry.hit(r, rec1.t + 0.0001, MAX) {
+        match self.boundry.hit(r, f32::MIN, f32::MAX) {
+            Some(mut rec1) => match self.boundry.hit(r, rec1.t + 0.0001, f32::MAX) {
                 Some(mut rec2) => {
                     if rec1.t < t_min {
                         rec1.t = t_min;
@@ -42,7 +42,7 @@ impl Hitable for ConstantMedium {
                         rec1.t = 0.0;
                     }
                     let distance_inside_boundary = (rec2.t - rec1.t) * r.direction().length();
-                    let hit_distance = -(1.0 / self.density) * drand48().ln();
+                    let hit_distance = -(1.0 / self.density) * Rng::from_ray(&r).drand48().ln();
                     if hit_distance < distance_inside_boundary {
                         let t = rec1.t + hit_distance / r.direction().length();
                         let p = r.point_at_parameter(t);
@@ -53,6 +53,7 @@ impl Hitable for ConstantMedium {
                             v: 0.0,
                             p,
                             normal,
+                            geometric_normal: normal,
                             material: self.phase_function.clone(),
                         })
                     } else {
@@ -67,4 +68,44 @@ impl Hitable for ConstantMedium {
     fn bounding_box(&self, t0: f32, t1: f32) -> Option<AABB> {
         self.boundry.bounding_box(t0, t1)
     }
+    fn collect_materials(&self, materials: &mut Vec<Arc<dyn Material>>) {
+        materials.push(Arc::clone(&self.phase_function));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::material::Lambertian;
+    use super::super::sphere::Sphere;
+    use super::super::texture::ConstantTexture;
+    use super::*;
+
+    fn medium() -> Box<ConstantMedium> {
+        let white = || Box::new(ConstantTexture::new(Vec3::new(1.0, 1.0, 1.0)));
+        let boundary = Sphere::new(
+            Vec3::new(0.0, 0.0, 0.0),
+            1.0,
+            Arc::new(Lambertian::new(white())),
+        );
+        ConstantMedium::new(Box::new(boundary), 0.5, white())
+    }
+
+    #[test]
+    fn scattering_follows_the_ray_seed() {
+        let medium = medium();
+        let ray = Ray::new(Vec3::new(0.0, 0.0, -5.0), Vec3::new(0.0, 0.0, 1.0), 0.0);
+        let t = |seed: u64| {
+            medium
+                .hit(ray.with_seed(seed), 0.001, f32::MAX)
+                .map(|rec| rec.t)
+        };
+        assert_eq!(t(3), t(3));
+        // Through 2 units at density 0.5, about 1 - e^-1 of the seeds scatter, at many depths.
+        let hits: Vec<f32> = (0..1000).filter_map(t).collect();
+        assert!(hits.len() > 580 && hits.len() < 680, "{}", hits.len());
+        let mut depths: Vec<u32> = hits.iter().map(|t| t.to_bits()).collect();
+        depths.sort_unstable();
+        depths.dedup();
+        assert!(depths.len() > 500);
+    }
 }
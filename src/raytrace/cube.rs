@@ -1,6 +1,7 @@
 use super::aabb::AABB;
-use super::hitable::{HitRecord, Hitable};
+use super::hitable::{HitRecord, Hitable, TraversalStats};
 use super::hitable_list::HitableList;
+use super::light::Light;
 use super::material::Material;
 use super::ray::Ray;
 use super::rectangle::{FlipNormal, XY, XZ, YZ};
@@ -8,7 +9,7 @@ use super::vec::Vec3;
 
 use std::sync::Arc;
 
-use std::f32::{consts::PI, MAX, MIN};
+use std::f32::consts::PI;
 
 pub struct Cube {
     p0: Vec3,
@@ -78,12 +79,27 @@ impl Hitable for Cube {
     fn hit(&self, r: Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
         self.rects.hit(r, t_min, t_max)
     }
+    fn hit_counted(
+        &self,
+        r: Ray,
+        t_min: f32,
+        t_max: f32,
+        stats: &mut TraversalStats,
+    ) -> Option<HitRecord> {
+        self.rects.hit_counted(r, t_min, t_max, stats)
+    }
     fn bounding_box(&self, _t0: f32, _t1: f32) -> Option<AABB> {
         Some(AABB {
             min: self.p0,
             max: self.p1,
         })
     }
+    fn collect_lights(&self, lights: &mut Vec<Arc<dyn Light>>) {
+        self.rects.collect_lights(lights)
+    }
+    fn collect_materials(&self, materials: &mut Vec<Arc<dyn Material>>) {
+        self.rects.collect_materials(materials)
+    }
 }
 pub struct Translate {
     p: Box<dyn Hitable>,
@@ -98,8 +114,20 @@ impl Translate {
 
 impl Hitable for Translate {
     fn hit(&self, r: Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
-        let moved_r = Ray::new(r.origin - self.displacement, r.direction, r.time);
-        match self.p.hit(moved_r, t_min, t_max) {
+        self.hit_counted(r, t_min, t_max, &mut TraversalStats::default())
+    }
+    fn hit_counted(
+        &self,
+        r: Ray,
+        t_min: f32,
+        t_max: f32,
+        stats: &mut TraversalStats,
+    ) -> Option<HitRecord> {
+        let moved_r = Ray {
+            origin: r.origin - self.displacement,
+            ..r
+        };
+        match self.p.hit_counted(moved_r, t_min, t_max, stats) {
             Some(rec) => Some(HitRecord {
                 p: rec.p + self.displacement,
                 ..rec
@@ -108,19 +136,42 @@ impl Hitable for Translate {
         }
     }
     fn bounding_box(&self, t0: f32, t1: f32) -> Option<AABB> {
-        match self.p.bounding_box(t0, t1) {
-            Some(bbox) => Some(AABB {
-                min: bbox.min + self.displacement,
-                max: bbox.max + self.displacement,
-            }),
-            None => None,
+        self.p.bounding_box(t0, t1).map(|bbox| AABB {
+            min: bbox.min + self.displacement,
+            max: bbox.max + self.displacement,
+        })
+    }
+    fn collect_materials(&self, materials: &mut Vec<Arc<dyn Material>>) {
+        self.p.collect_materials(materials)
+    }
+    fn collect_lights(&self, lights: &mut Vec<Arc<dyn Light>>) {
+        let mut inner = vec![];
+        self.p.collect_lights(&mut inner);
+        for light in inner {
+            lights.push(Arc::new(TranslatedLight {
+                light,
+                displacement: self.displacement,
+            }));
         }
     }
 }
 
+struct TranslatedLight {
+    light: Arc<dyn Light>,
+    displacement: Vec3,
+}
+
+impl Light for TranslatedLight {
+    fn pdf_value(&self, origin: Vec3, direction: Vec3) -> f32 {
+        self.light.pdf_value(origin - self.displacement, direction)
+    }
+    fn random(&self, origin: Vec3, u: (f32, f32)) -> Vec3 {
+        self.light.random(origin - self.displacement, u)
+    }
+}
+
 pub struct RotateY {
     p: Box<dyn Hitable>,
-    angle: f32,
     bbox: Option<AABB>,
     sin_theta: f32,
     cos_theta: f32,
@@ -133,8 +184,8 @@ impl RotateY {
         let cos_theta = radians.cos();
         let bbox = match p.bounding_box(0.0, 0.0) {
             Some(bbox) => {
-                let mut min = Vec3::new(MAX, MAX, MAX);
-                let mut max = Vec3::new(MIN, MIN, MIN);
+                let mut min = Vec3::new(f32::MAX, f32::MAX, f32::MAX);
+                let mut max = Vec3::new(f32::MIN, f32::MIN, f32::MIN);
                 for i in 0..2 {
                     for j in 0..2 {
                         for k in 0..2 {
@@ -164,7 +215,6 @@ impl RotateY {
         };
         Box::new(RotateY {
             p,
-            angle,
             bbox,
             sin_theta,
             cos_theta,
@@ -174,23 +224,47 @@ impl RotateY {
 
 impl Hitable for RotateY {
     fn hit(&self, r: Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+        self.hit_counted(r, t_min, t_max, &mut TraversalStats::default())
+    }
+
+    fn hit_counted(
+        &self,
+        r: Ray,
+        t_min: f32,
+        t_max: f32,
+        stats: &mut TraversalStats,
+    ) -> Option<HitRecord> {
         let mut origin = r.origin;
         let mut direction = r.direction;
         origin[0] = self.cos_theta * r.origin[0] - self.sin_theta * r.origin[2];
         origin[2] = self.sin_theta * r.origin[0] + self.cos_theta * r.origin[2];
         direction[0] = self.cos_theta * r.direction[0] - self.sin_theta * r.direction[2];
         direction[2] = self.sin_theta * r.direction[0] + self.cos_theta * r.direction[2];
-        let rotated_r = Ray::new(origin, direction, r.time());
+        let rotated_r = Ray {
+            origin,
+            direction,
+            ..r
+        };
 
-        match self.p.hit(rotated_r, t_min, t_max) {
+        match self.p.hit_counted(rotated_r, t_min, t_max, stats) {
             Some(rec) => {
                 let mut p = rec.p;
                 let mut normal = rec.normal;
+                let mut geometric_normal = rec.geometric_normal;
                 p[0] = self.cos_theta * rec.p[0] + self.sin_theta * rec.p[2];
                 p[2] = -self.sin_theta * rec.p[0] + self.cos_theta * rec.p[2];
                 normal[0] = self.cos_theta * rec.normal[0] + self.sin_theta * rec.normal[2];
                 normal[2] = -self.sin_theta * rec.normal[0] + self.cos_theta * rec.normal[2];
-                Some(HitRecord { p, normal, ..rec })
+                geometric_normal[0] = self.cos_theta * rec.geometric_normal[0]
+                    + self.sin_theta * rec.geometric_normal[2];
+                geometric_normal[2] = -self.sin_theta * rec.geometric_normal[0]
+                    + self.cos_theta * rec.geometric_normal[2];
+                Some(HitRecord {
+                    p,
+                    normal,
+                    geometric_normal,
+                    ..rec
+                })
             }
             None => None,
         }
@@ -199,4 +273,52 @@ impl Hitable for RotateY {
     fn bounding_box(&self, _t0: f32, _t1: f32) -> Option<AABB> {
         self.bbox
     }
+
+    fn collect_materials(&self, materials: &mut Vec<Arc<dyn Material>>) {
+        self.p.collect_materials(materials)
+    }
+    fn collect_lights(&self, lights: &mut Vec<Arc<dyn Light>>) {
+        let mut inner = vec![];
+        self.p.collect_lights(&mut inner);
+        for light in inner {
+            lights.push(Arc::new(RotatedLight {
+                light,
+                sin_theta: self.sin_theta,
+                cos_theta: self.cos_theta,
+            }));
+        }
+    }
+}
+
+struct RotatedLight {
+    light: Arc<dyn Light>,
+    sin_theta: f32,
+    cos_theta: f32,
+}
+
+impl RotatedLight {
+    fn to_object(&self, v: Vec3) -> Vec3 {
+        Vec3::new(
+            self.cos_theta * v.x() - self.sin_theta * v.z(),
+            v.y(),
+            self.sin_theta * v.x() + self.cos_theta * v.z(),
+        )
+    }
+    fn to_world(&self, v: Vec3) -> Vec3 {
+        Vec3::new(
+            self.cos_theta * v.x() + self.sin_theta * v.z(),
+            v.y(),
+            -self.sin_theta * v.x() + self.cos_theta * v.z(),
+        )
+    }
+}
+
+impl Light for RotatedLight {
+    fn pdf_value(&self, origin: Vec3, direction: Vec3) -> f32 {
+        self.light
+            .pdf_value(self.to_object(origin), self.to_object(direction))
+    }
+    fn random(&self, origin: Vec3, u: (f32, f32)) -> Vec3 {
+        self.to_world(self.light.random(self.to_object(origin), u))
+    }
 }
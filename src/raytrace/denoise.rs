@@ -0,0 +1,248 @@
+use super::aov::{Aov, AovFilms};
+use super::film::Film;
+use super::tonemap::luminance;
+use super::vec::Vec3;
+
+use rayon::prelude::*;
+
+// Edge-avoiding à-trous wavelet filter (Dammertz et al. 2010), guided by the first-hit
+// albedo, normal and depth buffers.
+#[derive(Clone, Copy, Debug)]
+pub struct DenoiseSettings {
+    // Each iteration doubles the filter footprint, starting from 5x5 pixels.
+    pub iterations: usize,
+    // Tolerances of the edge-stopping functions; smaller values keep sharper edges.
+    pub sigma_color: f32,
+    pub sigma_albedo: f32,
+    // Relative depth difference.
+    pub sigma_depth: f32,
+    // Exponent applied to the cosine between normals.
+    pub normal_power: f32,
+    // Before filtering, pixels more than this many times brighter than the second brightest
+    // pixel of their 5x5 neighbourhood are clamped to that, so lone fireflies do not spread.
+    // Features a few pixels across, down to thin lines, have bright neighbours and survive.
+    pub firefly_threshold: Option<f32>,
+}
+
+impl Default for DenoiseSettings {
+    fn default() -> Self {
+        DenoiseSettings {
+            iterations: 5,
+            sigma_color: 0.3,
+            sigma_albedo: 0.2,
+            sigma_depth: 0.05,
+            normal_power: 64.0,
+            firefly_threshold: Some(4.0),
+        }
+    }
+}
+
+const KERNEL: [f32; 5] = [1.0 / 16.0, 1.0 / 4.0, 3.0 / 8.0, 1.0 / 4.0, 1.0 / 16.0];
+
+struct Guide {
+    albedo: Vec3,
+    normal: Vec3,
+    depth: f32,
+}
+
+// Denoised radiance rows, top row first. Lighting is filtered with the albedo divided out
+// so that texture detail is not blurred, then multiplied back in.
+pub fn denoise(beauty: &Film, aovs: &AovFilms, settings: &DenoiseSettings) -> Vec<Vec<Vec3>> {
+    let width = beauty.width;
+    let height = beauty.height;
+    let guides: Vec<Guide> = (0..width * height)
+        .map(|i| {
+            let (x, y) = (i % width, i / width);
+            Guide {
+                albedo: aovs.film(Aov::Albedo).value(x, y),
+                normal: aovs.film(Aov::Normal).value(x, y),
+                depth: aovs.film(Aov::Depth).value(x, y).r(),
+            }
+        })
+        .collect();
+    let modulation: Vec<Vec3> = guides
+        .iter()
+        .map(|g| {
+            let a = g.albedo;
+            let f = |c: f32| if c > 0.01 { c } else { 1.0 };
+            Vec3::new(f(a.r()), f(a.g()), f(a.b()))
+        })
+        .collect();
+    let image: Vec<Vec3> = (0..width * height)
+        .map(|i| beauty.value(i % width, i / width) / modulation[i])
+        .collect();
+    let mut image = match settings.firefly_threshold {
+        Some(threshold) => remove_fireflies(&image, width, height, threshold),
+        None => image,
+    };
+
+    for iteration in 0..settings.iterations {
+        let step = 1 << iteration;
+        let sigma_color = settings.sigma_color / (1 << iteration) as f32;
+        image = (0..height)
+            .into_par_iter()
+            .flat_map(|y| {
+                let image = &image;
+                let guides = &guides;
+                (0..width)
+                    .map(|x| {
+                        filter_pixel(
+                            image,
+                            guides,
+                            width,
+                            height,
+                            x,
+                            y,
+                            step,
+                            sigma_color,
+                            settings,
+                        )
+                    })
+                    .collect::<Vec<Vec3>>()
+            })
+            .collect();
+    }
+
+    image
+        .chunks(width)
+        .zip(modulation.chunks(width))
+        .map(|(row, m)| row.iter().zip(m).map(|(c, a)| *c * *a).collect())
+        .collect()
+}
+
+#[allow(clippy::too_many_arguments)]
+fn filter_pixel(
+    image: &[Vec3],
+    guides: &[Guide],
+    width: usize,
+    height: usize,
+    x: usize,
+    y: usize,
+    step: usize,
+    sigma_color: f32,
+    settings: &DenoiseSettings,
+) -> Vec3 {
+    let p = y * width + x;
+    let color_p = compress(image[p]);
+    let guide_p = &guides[p];
+    let mut sum = Vec3::new(0.0, 0.0, 0.0);
+    let mut weight_sum = 0.0;
+    for (ky, hy) in KERNEL.iter().enumerate() {
+        let qy = y as isize + (ky as isize - 2) * step as isize;
+        if qy < 0 || qy >= height as isize {
+            continue;
+        }
+        for (kx, hx) in KERNEL.iter().enumerate() {
+            let qx = x as isize + (kx as isize - 2) * step as isize;
+            if qx < 0 || qx >= width as isize {
+                continue;
+            }
+            let q = qy as usize * width + qx as usize;
+            let guide_q = &guides[q];
+
+            let color = (color_p - compress(image[q])).squared_length();
+            let albedo = (guide_p.albedo - guide_q.albedo).squared_length();
+            let depth =
+                (guide_p.depth - guide_q.depth).abs() / guide_p.depth.max(guide_q.depth).max(1e-4);
+            let normal = Vec3::dot(&guide_p.normal, &guide_q.normal)
+                .max(0.0)
+                .powf(settings.normal_power);
+            // Background pixels have zero normals and only blend with each other.
+            let normal = if guide_p.normal.squared_length() == 0.0
+                && guide_q.normal.squared_length() == 0.0
+            {
+                1.0
+            } else {
+                normal
+            };
+
+            let w = hx
+                * hy
+                * (-color / (sigma_color * sigma_color)).exp()
+                * (-albedo / (settings.sigma_albedo * settings.sigma_albedo)).exp()
+                * (-depth / settings.sigma_depth).exp()
+                * normal;
+            sum += w * image[q];
+            weight_sum += w;
+        }
+    }
+    if weight_sum > 0.0 {
+        sum / weight_sum
+    } else {
+        image[p]
+    }
+}
+
+// Scales down each pixel whose luminance exceeds `threshold` times that of the second
+// brightest other pixel in its 5x5 neighbourhood, to that limit. Using the second brightest
+// lets a firefly the filter spread over two pixels be caught, while edges and thin lines,
+// which always have several bright neighbours, are left alone. Outliers would otherwise
+// survive every pass, since the color weight keeps them apart from their surroundings.
+fn remove_fireflies(image: &[Vec3], width: usize, height: usize, threshold: f32) -> Vec<Vec3> {
+    (0..width * height)
+        .map(|p| {
+            let (x, y) = ((p % width) as isize, (p / width) as isize);
+            let (mut first, mut second) = (0.0f32, 0.0f32);
+            for qy in (y - 2).max(0)..(y + 3).min(height as isize) {
+                for qx in (x - 2).max(0)..(x + 3).min(width as isize) {
+                    if qx == x && qy == y {
+                        continue;
+                    }
+                    let l = luminance(image[qy as usize * width + qx as usize]);
+                    if l > first {
+                        second = first;
+                        first = l;
+                    } else if l > second {
+                        second = l;
+                    }
+                }
+            }
+            let limit = threshold * second;
+            let l = luminance(image[p]);
+            if l > limit && l > 0.0 {
+                image[p] * (limit / l)
+            } else {
+                image[p]
+            }
+        })
+        .collect()
+}
+
+// Colors are compared after x / (1 + x), so fireflies do not dominate the distance.
+fn compress(c: Vec3) -> Vec3 {
+    Vec3::new(
+        c.r() / (1.0 + c.r().max(0.0)),
+        c.g() / (1.0 + c.g().max(0.0)),
+        c.b() / (1.0 + c.b().max(0.0)),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gray(v: f32) -> Vec3 {
+        Vec3::new(v, v, v)
+    }
+
+    #[test]
+    fn lone_firefly_is_clamped() {
+        let mut image = vec![gray(1.0); 25];
+        image[12] = gray(100.0);
+        let out = remove_fireflies(&image, 5, 5, 4.0);
+        assert!((luminance(out[12]) - 4.0).abs() < 1e-3);
+        assert!((luminance(out[0]) - 1.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn thin_bright_line_survives() {
+        let mut image = vec![gray(0.1); 25];
+        for x in 0..5 {
+            image[2 * 5 + x] = gray(50.0);
+        }
+        let out = remove_fireflies(&image, 5, 5, 4.0);
+        for x in 0..5 {
+            assert!((luminance(out[2 * 5 + x]) - 50.0).abs() < 1e-2);
+        }
+    }
+}
@@ -0,0 +1,155 @@
+// Piecewise-constant distributions for importance sampling tabulated functions, after pbrt's
+// Distribution1D and Distribution2D.
+pub struct Distribution1D {
+    func: Vec<f32>,
+    // cdf[i] is the probability of landing before segment i; it has one more entry than func.
+    cdf: Vec<f32>,
+    // Integral of the function over [0, 1].
+    integral: f32,
+}
+
+impl Distribution1D {
+    pub fn new(func: &[f32]) -> Self {
+        let n = func.len();
+        let func: Vec<f32> = func.iter().map(|f| f.abs()).collect();
+        let mut cdf = vec![0.0; n + 1];
+        for i in 0..n {
+            cdf[i + 1] = cdf[i] + func[i] / n as f32;
+        }
+        let integral = cdf[n];
+        // An all-zero function is sampled uniformly.
+        for (i, c) in cdf.iter_mut().enumerate() {
+            *c = if integral > 0.0 {
+                *c / integral
+            } else {
+                i as f32 / n as f32
+            };
+        }
+        Distribution1D {
+            func,
+            cdf,
+            integral,
+        }
+    }
+
+    pub fn count(&self) -> usize {
+        self.func.len()
+    }
+
+    pub fn integral(&self) -> f32 {
+        self.integral
+    }
+
+    // Point in [0, 1) distributed like the function, its density and the segment it fell in.
+    pub fn sample(&self, u: f32) -> (f32, f32, usize) {
+        let n = self.count();
+        // Last segment whose cdf does not exceed u.
+        let offset = self.cdf[1..n].partition_point(|c| *c <= u);
+        let width = self.cdf[offset + 1] - self.cdf[offset];
+        let du = if width > 0.0 {
+            (u - self.cdf[offset]) / width
+        } else {
+            0.0
+        };
+        let x = ((offset as f32 + du) / n as f32).min(1.0 - f32::EPSILON);
+        (x, self.pdf_segment(offset), offset)
+    }
+
+    // Density at a point of segment `index`.
+    pub fn pdf_segment(&self, index: usize) -> f32 {
+        if self.integral > 0.0 {
+            self.func[index] / self.integral
+        } else {
+            1.0
+        }
+    }
+}
+
+// Distribution over [0, 1]^2 from a function tabulated on a `width` by `height` grid, given
+// row by row. The second coordinate is chosen first from the marginal over rows.
+pub struct Distribution2D {
+    conditional: Vec<Distribution1D>,
+    marginal: Distribution1D,
+}
+
+impl Distribution2D {
+    pub fn new(func: &[f32], width: usize, height: usize) -> Self {
+        let conditional: Vec<Distribution1D> = func
+            .chunks(width)
+            .take(height)
+            .map(Distribution1D::new)
+            .collect();
+        let marginal: Vec<f32> = conditional.iter().map(|d| d.integral()).collect();
+        Distribution2D {
+            conditional,
+            marginal: Distribution1D::new(&marginal),
+        }
+    }
+
+    // Point distributed like the function and its density with respect to area in [0, 1]^2.
+    pub fn sample(&self, u: (f32, f32)) -> ((f32, f32), f32) {
+        let (v, pdf_v, row) = self.marginal.sample(u.1);
+        let (u, pdf_u, _) = self.conditional[row].sample(u.0);
+        ((u, v), pdf_u * pdf_v)
+    }
+
+    pub fn pdf(&self, u: f32, v: f32) -> f32 {
+        let height = self.marginal.count();
+        let row = ((v * height as f32) as usize).min(height - 1);
+        let conditional = &self.conditional[row];
+        let width = conditional.count();
+        let column = ((u * width as f32) as usize).min(width - 1);
+        conditional.pdf_segment(column) * self.marginal.pdf_segment(row)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A 6 by 4 table with an empty row and an empty cell.
+    fn table() -> Vec<f32> {
+        let mut func: Vec<f32> = (0..24).map(|i| 1.0 + (i % 7) as f32).collect();
+        for f in &mut func[6..12] {
+            *f = 0.0;
+        }
+        func[20] = 0.0;
+        func
+    }
+
+    #[test]
+    fn sampled_density_matches_pdf() {
+        let distribution = Distribution2D::new(&table(), 6, 4);
+        for i in 0..64 {
+            for j in 0..64 {
+                let u = ((i as f32 + 0.5) / 64.0, (j as f32 + 0.5) / 64.0);
+                let ((x, y), pdf) = distribution.sample(u);
+                assert!((0.0..1.0).contains(&x) && (0.0..1.0).contains(&y));
+                assert!(pdf > 0.0);
+                assert!((pdf - distribution.pdf(x, y)).abs() < 1e-4 * pdf);
+            }
+        }
+    }
+
+    #[test]
+    fn pdf_integrates_to_one() {
+        let distribution = Distribution2D::new(&table(), 6, 4);
+        let n = 120;
+        let integral: f32 = (0..n * n)
+            .map(|i| {
+                let (x, y) = ((i % n) as f32 + 0.5, (i / n) as f32 + 0.5);
+                distribution.pdf(x / n as f32, y / n as f32)
+            })
+            .sum::<f32>()
+            / (n * n) as f32;
+        assert!((integral - 1.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn zero_function_is_sampled_uniformly() {
+        let distribution = Distribution1D::new(&[0.0; 4]);
+        let (x, pdf, index) = distribution.sample(0.6);
+        assert!((x - 0.6).abs() < 1e-6);
+        assert_eq!((pdf, index), (1.0, 2));
+    }
+}
@@ -0,0 +1,218 @@
+use super::light::Light;
+use super::vec::Vec3;
+
+use std::sync::Arc;
+
+// Light arriving from infinitely far away, seen by rays that leave the scene.
+pub trait Environment: Sync + Send {
+    // Radiance arriving from `direction`, which need not be normalized.
+    fn radiance(&self, direction: Vec3) -> Vec3;
+    // Environments that can be importance sampled are also used as a light, so direct
+    // lighting aims shadow rays at their bright parts.
+    fn is_samplable(&self) -> bool {
+        false
+    }
+    // Direction to receive light from, roughly in proportion to its radiance.
+    fn sample(&self, _u: (f32, f32)) -> Vec3 {
+        Vec3::new(0.0, 1.0, 0.0)
+    }
+    // Solid angle density of `sample` returning `direction`.
+    fn pdf(&self, _direction: Vec3) -> f32 {
+        0.0
+    }
+}
+
+// What produced a ray that leaves the scene: the camera, a specular reflection or refraction,
+// or any other scattering.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RayKind {
+    Camera,
+    Specular,
+    Diffuse,
+}
+
+// The environment seen by each kind of ray, so that the backdrop behind objects and their
+// reflections can differ from the light they receive. Only the diffuse environment is light
+// sampled.
+#[derive(Clone)]
+pub struct Environments {
+    pub camera: Arc<dyn Environment>,
+    pub specular: Arc<dyn Environment>,
+    pub diffuse: Arc<dyn Environment>,
+}
+
+impl Environments {
+    // The same environment for every ray.
+    pub fn new(environment: Arc<dyn Environment>) -> Self {
+        Environments {
+            camera: Arc::clone(&environment),
+            specular: Arc::clone(&environment),
+            diffuse: environment,
+        }
+    }
+
+    pub fn with_camera(mut self, environment: Arc<dyn Environment>) -> Self {
+        self.camera = environment;
+        self
+    }
+
+    pub fn with_specular(mut self, environment: Arc<dyn Environment>) -> Self {
+        self.specular = environment;
+        self
+    }
+
+    pub fn get(&self, kind: RayKind) -> &dyn Environment {
+        match kind {
+            RayKind::Camera => self.camera.as_ref(),
+            RayKind::Specular => self.specular.as_ref(),
+            RayKind::Diffuse => self.diffuse.as_ref(),
+        }
+    }
+}
+
+// A samplable environment seen as one of the scene's lights. Its directions do not depend on
+// the point being lit.
+pub struct EnvironmentLight {
+    environment: Arc<dyn Environment>,
+}
+
+impl EnvironmentLight {
+    pub fn new(environment: Arc<dyn Environment>) -> Self {
+        EnvironmentLight { environment }
+    }
+}
+
+impl Light for EnvironmentLight {
+    fn pdf_value(&self, _origin: Vec3, direction: Vec3) -> f32 {
+        self.environment.pdf(direction)
+    }
+    fn random(&self, _origin: Vec3, u: (f32, f32)) -> Vec3 {
+        self.environment.sample(u)
+    }
+}
+
+// Black, for closed scenes such as the Cornell box that are lit by their own lights.
+pub struct NoEnvironment;
+
+impl Environment for NoEnvironment {
+    fn radiance(&self, _direction: Vec3) -> Vec3 {
+        Vec3::new(0.0, 0.0, 0.0)
+    }
+}
+
+// The same radiance from every direction.
+pub struct ConstantEnvironment {
+    pub color: Vec3,
+}
+
+impl ConstantEnvironment {
+    pub fn new(color: Vec3) -> Self {
+        ConstantEnvironment { color }
+    }
+}
+
+impl Environment for ConstantEnvironment {
+    fn radiance(&self, _direction: Vec3) -> Vec3 {
+        self.color
+    }
+}
+
+// Blends from `bottom` straight down to `top` straight up, linearly in the direction's height.
+pub struct GradientEnvironment {
+    pub bottom: Vec3,
+    pub top: Vec3,
+}
+
+impl GradientEnvironment {
+    pub fn new(bottom: Vec3, top: Vec3) -> Self {
+        GradientEnvironment { bottom, top }
+    }
+}
+
+// The white to light blue sky of the outdoor scenes.
+impl Default for GradientEnvironment {
+    fn default() -> Self {
+        GradientEnvironment::new(Vec3::new(1.0, 1.0, 1.0), Vec3::new(0.5, 0.7, 1.0))
+    }
+}
+
+impl Environment for GradientEnvironment {
+    fn radiance(&self, direction: Vec3) -> Vec3 {
+        let t = 0.5 * (direction.normalize().y() + 1.0);
+        (1.0 - t) * self.bottom + t * self.top
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::skymap::IBLSkyMap;
+    use super::*;
+
+    fn close(a: Vec3, b: Vec3) -> bool {
+        (a - b).length() < 1e-5
+    }
+
+    #[test]
+    fn simple_environments() {
+        let direction = Vec3::new(0.3, -2.0, 0.1);
+        assert!(close(
+            NoEnvironment.radiance(direction),
+            Vec3::new(0.0, 0.0, 0.0)
+        ));
+        let color = Vec3::new(0.2, 0.4, 0.8);
+        assert!(close(
+            ConstantEnvironment::new(color).radiance(direction),
+            color
+        ));
+        for environment in &[
+            &NoEnvironment as &dyn Environment,
+            &ConstantEnvironment::new(color),
+        ] {
+            assert!(!environment.is_samplable());
+        }
+    }
+
+    #[test]
+    fn gradient_runs_from_bottom_to_top() {
+        let (bottom, top) = (Vec3::new(1.0, 0.5, 0.0), Vec3::new(0.0, 0.5, 1.0));
+        let gradient = GradientEnvironment::new(bottom, top);
+        assert!(close(gradient.radiance(Vec3::new(0.0, -3.0, 0.0)), bottom));
+        assert!(close(gradient.radiance(Vec3::new(0.0, 0.2, 0.0)), top));
+        let horizon = gradient.radiance(Vec3::new(5.0, 0.0, -1.0));
+        assert!(close(horizon, 0.5 * (bottom + top)));
+    }
+
+    #[test]
+    fn environment_light_forwards_to_the_environment() {
+        let mut pixels = vec![Vec3::new(0.1, 0.1, 0.1); 8 * 4];
+        pixels[9] = Vec3::new(20.0, 20.0, 20.0);
+        let map: Arc<dyn Environment> = Arc::new(IBLSkyMap::from_pixels(pixels, 8, 4));
+        let light = EnvironmentLight::new(Arc::clone(&map));
+        let origin = Vec3::new(3.0, -1.0, 2.0);
+        for &u in &[(0.1, 0.2), (0.5, 0.5), (0.9, 0.7)] {
+            let direction = light.random(origin, u);
+            assert!(close(direction, map.sample(u)));
+            assert_eq!(light.pdf_value(origin, direction), map.pdf(direction));
+        }
+    }
+
+    #[test]
+    fn each_ray_kind_gets_its_environment() {
+        let constant = |v: f32| Arc::new(ConstantEnvironment::new(Vec3::new(v, v, v)));
+        let environments = Environments::new(constant(3.0))
+            .with_camera(constant(1.0))
+            .with_specular(constant(2.0));
+        let up = Vec3::new(0.0, 1.0, 0.0);
+        for &(kind, v) in &[
+            (RayKind::Camera, 1.0),
+            (RayKind::Specular, 2.0),
+            (RayKind::Diffuse, 3.0),
+        ] {
+            assert_eq!(environments.get(kind).radiance(up).g(), v, "{:?}", kind);
+        }
+        let same = Environments::new(constant(4.0));
+        for kind in &[RayKind::Camera, RayKind::Specular, RayKind::Diffuse] {
+            assert_eq!(same.get(*kind).radiance(up).g(), 4.0, "{:?}", kind);
+        }
+    }
+}
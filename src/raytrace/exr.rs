@@ -0,0 +1,532 @@
+extern crate inflate;
+
+use super::vec::Vec3;
+
+use std::fs;
+use std::io;
+use std::path::Path;
+
+const MAGIC: [u8; 4] = [0x76, 0x2f, 0x31, 0x01];
+
+// Flags in the version field for tiled, deep and multi-part files.
+const UNSUPPORTED_FLAGS: u32 = 0x200 | 0x800 | 0x1000;
+
+// Largest data window read, in pixels per side and in total. The total is that of a 16K by 8K
+// environment map, and keeps a corrupt header from asking for an enormous allocation.
+const MAX_SIDE: i64 = 1 << 16;
+const MAX_PIXELS: i64 = 1 << 27;
+
+#[derive(Clone, Copy, PartialEq)]
+enum Compression {
+    None,
+    Rle,
+    Zips,
+    Zip,
+}
+
+impl Compression {
+    fn lines_per_block(self) -> usize {
+        match self {
+            Compression::Zip => 16,
+            _ => 1,
+        }
+    }
+}
+
+struct Channel {
+    name: String,
+    // Bytes per sample: 2 for half, 4 for float and uint.
+    size: usize,
+    pixel_type: i32,
+}
+
+// Reads the R, G and B channels of a single-part scanline OpenEXR image, uncompressed or
+// compressed with RLE or ZIP. Returns the pixels top row first with the width and height. An
+// image with only a Y channel is read as grey.
+pub fn read(path: &Path) -> io::Result<(Vec<Vec3>, usize, usize)> {
+    parse(&fs::read(path)?)
+}
+
+fn parse(data: &[u8]) -> io::Result<(Vec<Vec3>, usize, usize)> {
+    let mut input = Input { data, pos: 0 };
+    if input.bytes(4)? != MAGIC {
+        return Err(invalid("not an OpenEXR file"));
+    }
+    if input.u32()? & UNSUPPORTED_FLAGS != 0 {
+        return Err(invalid(
+            "tiled, deep and multi-part OpenEXR files are not supported",
+        ));
+    }
+
+    let mut channels = Vec::new();
+    let mut compression = None;
+    let mut data_window = None;
+    loop {
+        let name = input.string()?;
+        if name.is_empty() {
+            break;
+        }
+        let kind = input.string()?;
+        let size = input.u32()? as usize;
+        let value = input.bytes(size)?;
+        let mut value = Input {
+            data: value,
+            pos: 0,
+        };
+        match (name.as_str(), kind.as_str()) {
+            ("channels", "chlist") => loop {
+                let name = value.string()?;
+                if name.is_empty() {
+                    break;
+                }
+                let pixel_type = value.i32()?;
+                value.bytes(4)?;
+                let (x_sampling, y_sampling) = (value.i32()?, value.i32()?);
+                if x_sampling != 1 || y_sampling != 1 {
+                    return Err(invalid("subsampled channels are not supported"));
+                }
+                let size = match pixel_type {
+                    0 | 2 => 4,
+                    1 => 2,
+                    _ => return Err(invalid("unknown channel pixel type")),
+                };
+                channels.push(Channel {
+                    name,
+                    size,
+                    pixel_type,
+                });
+            },
+            ("compression", "compression") => {
+                compression = Some(match value.bytes(1)?[0] {
+                    0 => Compression::None,
+                    1 => Compression::Rle,
+                    2 => Compression::Zips,
+                    3 => Compression::Zip,
+                    n => {
+                        return Err(invalid(&format!(
+                            "compression method {} is not supported, only none, RLE and ZIP",
+                            n
+                        )))
+                    }
+                });
+            }
+            ("dataWindow", "box2i") => {
+                data_window = Some((value.i32()?, value.i32()?, value.i32()?, value.i32()?));
+            }
+            _ => {}
+        }
+    }
+    let compression = compression.ok_or_else(|| invalid("missing compression attribute"))?;
+    let (x_min, y_min, x_max, y_max) =
+        data_window.ok_or_else(|| invalid("missing dataWindow attribute"))?;
+    if x_max < x_min || y_max < y_min {
+        return Err(invalid("empty data window"));
+    }
+    let width = x_max as i64 - x_min as i64 + 1;
+    let height = y_max as i64 - y_min as i64 + 1;
+    if width > MAX_SIDE || height > MAX_SIDE || width * height > MAX_PIXELS {
+        return Err(invalid(&format!(
+            "data window of {} by {} pixels is too large",
+            width, height
+        )));
+    }
+    let (width, height) = (width as usize, height as usize);
+
+    // Where each of R, G and B is found in the channel list, which is sorted by name.
+    let find = |name: &str| channels.iter().position(|c| c.name == name);
+    let rgb = match (find("R"), find("G"), find("B"), find("Y")) {
+        (Some(r), Some(g), Some(b), _) => [r, g, b],
+        (_, _, _, Some(y)) => [y, y, y],
+        _ => return Err(invalid("no R, G, B or Y channels")),
+    };
+    let pixel_bytes: usize = channels.iter().map(|c| c.size).sum();
+    let line_bytes = pixel_bytes * width;
+
+    let lines = compression.lines_per_block();
+    let blocks = height.div_ceil(lines);
+    let offsets = (0..blocks)
+        .map(|_| input.u64())
+        .collect::<io::Result<Vec<u64>>>()?;
+    let mut pixels = vec![Vec3::new(0.0, 0.0, 0.0); width * height];
+    for offset in offsets {
+        let mut block = Input {
+            data,
+            pos: offset as usize,
+        };
+        let y = block.i32()? as i64 - y_min as i64;
+        let size = block.u32()? as usize;
+        let packed = block.bytes(size)?;
+        if y < 0 || y >= height as i64 {
+            return Err(invalid("scanline block outside the data window"));
+        }
+        let y = y as usize;
+        let count = lines.min(height - y);
+        let expected = count * line_bytes;
+        let raw = if compression == Compression::None || size == expected {
+            packed.to_vec()
+        } else {
+            let bytes = match compression {
+                Compression::Rle => unpack_rle(packed)?,
+                _ => inflate::inflate_bytes_zlib(packed).map_err(|err| invalid(&err))?,
+            };
+            reorder(&bytes)
+        };
+        if raw.len() != expected {
+            return Err(invalid("scanline block has the wrong size"));
+        }
+
+        // Each line stores all of a channel's samples before the next channel's.
+        for line in 0..count {
+            let line_data = &raw[line * line_bytes..(line + 1) * line_bytes];
+            let mut start = 0;
+            for (c, channel) in channels.iter().enumerate() {
+                for (k, index) in rgb.iter().enumerate() {
+                    if *index != c {
+                        continue;
+                    }
+                    for x in 0..width {
+                        let sample = &line_data[start + x * channel.size..];
+                        pixels[(y + line) * width + x][k] = decode(channel.pixel_type, sample);
+                    }
+                }
+                start += channel.size * width;
+            }
+        }
+    }
+    Ok((pixels, width, height))
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+fn decode(pixel_type: i32, b: &[u8]) -> f32 {
+    match pixel_type {
+        0 => u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f32,
+        1 => half_to_f32(u16::from_le_bytes([b[0], b[1]])),
+        _ => f32::from_le_bytes([b[0], b[1], b[2], b[3]]),
+    }
+}
+
+fn half_to_f32(h: u16) -> f32 {
+    let sign = if h & 0x8000 != 0 { -1.0 } else { 1.0 };
+    let exponent = ((h >> 10) & 0x1f) as i32;
+    let mantissa = (h & 0x3ff) as f32;
+    match exponent {
+        0 => sign * mantissa * 2f32.powi(-24),
+        31 if mantissa == 0.0 => sign * f32::INFINITY,
+        31 => f32::NAN,
+        _ => sign * (1.0 + mantissa / 1024.0) * 2f32.powi(exponent - 15),
+    }
+}
+
+// A negative count is followed by that many literal bytes, a count n >= 0 by one byte to
+// repeat n + 1 times.
+fn unpack_rle(packed: &[u8]) -> io::Result<Vec<u8>> {
+    let mut out = Vec::new();
+    let mut i = 0;
+    while i < packed.len() {
+        let count = packed[i] as i8;
+        i += 1;
+        if count < 0 {
+            let end = i + (-(count as i32)) as usize;
+            out.extend_from_slice(packed.get(i..end).ok_or_else(|| invalid("truncated RLE"))?);
+            i = end;
+        } else {
+            let value = *packed.get(i).ok_or_else(|| invalid("truncated RLE"))?;
+            out.resize(out.len() + count as usize + 1, value);
+            i += 1;
+        }
+    }
+    Ok(out)
+}
+
+// Undoes the delta predictor and the split into even and odd bytes that RLE and ZIP apply
+// before compressing.
+fn reorder(bytes: &[u8]) -> Vec<u8> {
+    let mut t = bytes.to_vec();
+    for i in 1..t.len() {
+        t[i] = t[i - 1].wrapping_add(t[i]).wrapping_sub(128);
+    }
+    let half = t.len().div_ceil(2);
+    let mut out = Vec::with_capacity(t.len());
+    for i in 0..half {
+        out.push(t[i]);
+        if half + i < t.len() {
+            out.push(t[half + i]);
+        }
+    }
+    out
+}
+
+struct Input<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Input<'a> {
+    fn bytes(&mut self, n: usize) -> io::Result<&'a [u8]> {
+        let bytes = self
+            .pos
+            .checked_add(n)
+            .and_then(|end| self.data.get(self.pos..end))
+            .ok_or_else(|| invalid("unexpected end of file"))?;
+        self.pos += n;
+        Ok(bytes)
+    }
+
+    fn u32(&mut self) -> io::Result<u32> {
+        let b = self.bytes(4)?;
+        Ok(u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    fn i32(&mut self) -> io::Result<i32> {
+        Ok(self.u32()? as i32)
+    }
+
+    fn u64(&mut self) -> io::Result<u64> {
+        let b = self.bytes(8)?;
+        let mut v = [0; 8];
+        v.copy_from_slice(b);
+        Ok(u64::from_le_bytes(v))
+    }
+
+    // Null-terminated string.
+    fn string(&mut self) -> io::Result<String> {
+        let rest = &self.data[self.pos.min(self.data.len())..];
+        let end = rest
+            .iter()
+            .position(|b| *b == 0)
+            .ok_or_else(|| invalid("unterminated string"))?;
+        let s = String::from_utf8_lossy(&rest[..end]).into_owned();
+        self.pos += end + 1;
+        Ok(s)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Writes a scanline image in the layout `parse` reads, with the channels named in `names`
+    // (sorted) all holding the same `pixel_type`.
+    fn write(
+        pixels: &[[f32; 3]],
+        width: usize,
+        names: &[&str],
+        pixel_type: i32,
+        compression: u8,
+        origin: (i32, i32),
+    ) -> Vec<u8> {
+        let height = pixels.len() / width;
+        let mut out = MAGIC.to_vec();
+        out.extend_from_slice(&2u32.to_le_bytes());
+        let mut attribute = |name: &str, kind: &str, value: &[u8]| {
+            out.extend_from_slice(name.as_bytes());
+            out.push(0);
+            out.extend_from_slice(kind.as_bytes());
+            out.push(0);
+            out.extend_from_slice(&(value.len() as u32).to_le_bytes());
+            out.extend_from_slice(value);
+        };
+        let mut chlist = Vec::new();
+        for name in names {
+            chlist.extend_from_slice(name.as_bytes());
+            chlist.push(0);
+            chlist.extend_from_slice(&pixel_type.to_le_bytes());
+            chlist.extend_from_slice(&[0; 4]);
+            chlist.extend_from_slice(&1i32.to_le_bytes());
+            chlist.extend_from_slice(&1i32.to_le_bytes());
+        }
+        chlist.push(0);
+        attribute("channels", "chlist", &chlist);
+        attribute("compression", "compression", &[compression]);
+        let window = [
+            origin.0,
+            origin.1,
+            origin.0 + width as i32 - 1,
+            origin.1 + height as i32 - 1,
+        ];
+        let window: Vec<u8> = window.iter().flat_map(|v| v.to_le_bytes()).collect();
+        attribute("dataWindow", "box2i", &window);
+        attribute("displayWindow", "box2i", &window);
+        attribute("lineOrder", "lineOrder", &[0]);
+        out.push(0);
+
+        let lines = if compression == 3 { 16 } else { 1 };
+        let blocks: Vec<Vec<u8>> = (0..height)
+            .step_by(lines)
+            .map(|y0| {
+                let mut raw = Vec::new();
+                for y in y0..(y0 + lines).min(height) {
+                    for name in names {
+                        let k = match *name {
+                            "R" | "Y" => 0,
+                            "G" => 1,
+                            _ => 2,
+                        };
+                        for x in 0..width {
+                            let v = pixels[y * width + x][k];
+                            match pixel_type {
+                                1 => raw.extend_from_slice(&f32_to_half(v).to_le_bytes()),
+                                _ => raw.extend_from_slice(&v.to_le_bytes()),
+                            }
+                        }
+                    }
+                }
+                match compression {
+                    0 => raw,
+                    1 => pack_rle(&predict(&raw)),
+                    _ => zlib_stored(&predict(&raw)),
+                }
+            })
+            .collect();
+        let mut offset = out.len() + 8 * blocks.len();
+        for block in &blocks {
+            out.extend_from_slice(&(offset as u64).to_le_bytes());
+            offset += 8 + block.len();
+        }
+        for (i, block) in blocks.iter().enumerate() {
+            out.extend_from_slice(&(origin.1 + (i * lines) as i32).to_le_bytes());
+            out.extend_from_slice(&(block.len() as u32).to_le_bytes());
+            out.extend_from_slice(block);
+        }
+        out
+    }
+
+    // Exact for the normal values the tests use.
+    fn f32_to_half(v: f32) -> u16 {
+        if v == 0.0 {
+            return 0;
+        }
+        let bits = v.to_bits();
+        let sign = ((bits >> 16) & 0x8000) as u16;
+        let exponent = (((bits >> 23) & 0xff) as i32 - 127 + 15) as u16;
+        sign | (exponent << 10) | ((bits >> 13) & 0x3ff) as u16
+    }
+
+    // The inverse of `reorder`.
+    fn predict(bytes: &[u8]) -> Vec<u8> {
+        let mut t: Vec<u8> = bytes.iter().step_by(2).copied().collect();
+        t.extend(bytes.iter().skip(1).step_by(2));
+        let mut out = t.clone();
+        for i in 1..t.len() {
+            out[i] = t[i].wrapping_sub(t[i - 1]).wrapping_add(128);
+        }
+        out
+    }
+
+    fn pack_rle(bytes: &[u8]) -> Vec<u8> {
+        let mut out = Vec::new();
+        let mut i = 0;
+        while i < bytes.len() {
+            let run = bytes[i..]
+                .iter()
+                .take(128)
+                .take_while(|b| **b == bytes[i])
+                .count();
+            if run >= 3 {
+                out.push((run - 1) as u8);
+                out.push(bytes[i]);
+                i += run;
+            } else {
+                let n = (bytes.len() - i).min(127);
+                out.push((-(n as i32)) as u8);
+                out.extend_from_slice(&bytes[i..i + n]);
+                i += n;
+            }
+        }
+        out
+    }
+
+    // A zlib stream of uncompressed deflate blocks.
+    fn zlib_stored(bytes: &[u8]) -> Vec<u8> {
+        let mut out = vec![0x78, 0x01];
+        let chunks: Vec<&[u8]> = bytes.chunks(0xffff).collect();
+        for (i, chunk) in chunks.iter().enumerate() {
+            out.push((i + 1 == chunks.len()) as u8);
+            out.extend_from_slice(&(chunk.len() as u16).to_le_bytes());
+            out.extend_from_slice(&(!(chunk.len() as u16)).to_le_bytes());
+            out.extend_from_slice(chunk);
+        }
+        let (mut a, mut b) = (1u32, 0u32);
+        for byte in bytes {
+            a = (a + *byte as u32) % 65521;
+            b = (b + a) % 65521;
+        }
+        out.extend_from_slice(&((b << 16) | a).to_be_bytes());
+        out
+    }
+
+    // A 5 by 20 image of values exact in half precision, with flat runs for RLE to find.
+    fn image() -> Vec<[f32; 3]> {
+        (0..100)
+            .map(|i| {
+                let (x, y) = ((i % 5) as f32, (i / 5) as f32);
+                [x * 0.25, y * 0.5, if y < 10.0 { 1.0 } else { 2.0 }]
+            })
+            .collect()
+    }
+
+    #[test]
+    fn round_trips_every_compression_and_type() {
+        let expected = image();
+        for &compression in &[0, 1, 2, 3] {
+            for &pixel_type in &[1, 2] {
+                let data = write(
+                    &expected,
+                    5,
+                    &["B", "G", "R"],
+                    pixel_type,
+                    compression,
+                    (-2, 3),
+                );
+                let (pixels, width, height) = parse(&data).unwrap();
+                assert_eq!((width, height), (5, 20));
+                for (p, e) in pixels.iter().zip(&expected) {
+                    assert_eq!(
+                        [p.r(), p.g(), p.b()],
+                        *e,
+                        "compression {}, type {}",
+                        compression,
+                        pixel_type
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn luminance_only_images_are_grey() {
+        let expected = image();
+        let data = write(&expected, 5, &["Y"], 1, 3, (0, 0));
+        let (pixels, _, _) = parse(&data).unwrap();
+        for (p, e) in pixels.iter().zip(&expected) {
+            assert_eq!([p.r(), p.g(), p.b()], [e[0], e[0], e[0]]);
+        }
+    }
+
+    #[test]
+    fn rejects_oversized_and_broken_files() {
+        let data = write(&image(), 5, &["B", "G", "R"], 2, 0, (0, 0));
+        let window = data
+            .windows(b"box2i\0".len())
+            .position(|w| w == b"box2i\0")
+            .unwrap()
+            + b"box2i\0".len()
+            + 4;
+        for &(x_min, x_max) in &[(0, 1 << 20), (i32::MIN, i32::MAX)] {
+            let mut data = data.clone();
+            data[window..window + 4].copy_from_slice(&x_min.to_le_bytes());
+            data[window + 8..window + 12].copy_from_slice(&x_max.to_le_bytes());
+            assert!(parse(&data).is_err());
+        }
+        for len in &[3, 40, data.len() / 2, data.len() - 1] {
+            assert!(parse(&data[..*len]).is_err());
+        }
+        let mut data = data;
+        let offsets = data.len() - 20 * (8 + 5 * 12) - 20 * 8;
+        data[offsets..offsets + 8].copy_from_slice(&u64::MAX.to_le_bytes());
+        assert!(parse(&data).is_err());
+    }
+}
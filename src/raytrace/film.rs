@@ -0,0 +1,321 @@
+use super::filter::Filter;
+use super::tonemap::luminance;
+use super::vec::Vec3;
+
+// Weight sums below this give no usable estimate. Filters with negative lobes, like Mitchell
+// and Lanczos, can bring a pixel's sum close to zero or below with few samples, where dividing
+// by it would blow up or flip the sign.
+const MIN_WEIGHT: f32 = 1e-4;
+
+#[derive(Clone, Copy, Debug)]
+pub struct FilmPixel {
+    // Filter-weighted sum of the samples and the sum of their weights.
+    pub sum: Vec3,
+    pub weight: f32,
+}
+
+// Accumulates filtered samples for the pixels in [x0, x1) x [y0, y1) of a `width` by `height`
+// image. Raster coordinates have y pointing down, so row 0 is the top of the image. Films over
+// different regions, or from different passes, are combined with `merge`.
+//
+// A film can also keep several buckets per pixel, each summing its own subset of the samples.
+// The pixel value is then the median of the bucket means, which rejects the rare huge samples
+// a plain mean would keep as fireflies.
+#[derive(Clone)]
+pub struct Film {
+    pub width: usize,
+    pub height: usize,
+    x0: usize,
+    y0: usize,
+    x1: usize,
+    y1: usize,
+    buckets: usize,
+    // Buckets of a pixel are stored next to each other.
+    pixels: Vec<FilmPixel>,
+}
+
+impl Film {
+    pub fn new(width: usize, height: usize) -> Self {
+        Film::with_bounds(width, height, 0, 0, width, height)
+    }
+
+    pub fn with_bounds(
+        width: usize,
+        height: usize,
+        x0: usize,
+        y0: usize,
+        x1: usize,
+        y1: usize,
+    ) -> Self {
+        let x1 = x1.min(width).max(x0);
+        let y1 = y1.min(height).max(y0);
+        let empty = FilmPixel {
+            sum: Vec3::new(0.0, 0.0, 0.0),
+            weight: 0.0,
+        };
+        Film {
+            width,
+            height,
+            x0,
+            y0,
+            x1,
+            y1,
+            buckets: 1,
+            pixels: vec![empty; (x1 - x0) * (y1 - y0)],
+        }
+    }
+
+    // The same film with `buckets` sample buckets per pixel, cleared.
+    pub fn with_buckets(mut self, buckets: usize) -> Self {
+        let buckets = buckets.max(1);
+        let empty = FilmPixel {
+            sum: Vec3::new(0.0, 0.0, 0.0),
+            weight: 0.0,
+        };
+        self.pixels = vec![empty; (self.x1 - self.x0) * (self.y1 - self.y0) * buckets];
+        self.buckets = buckets;
+        self
+    }
+
+    // Film for the pixels a tile's samples can reach through a filter of the given radius.
+    pub fn for_tile(
+        width: usize,
+        height: usize,
+        x0: usize,
+        y0: usize,
+        x1: usize,
+        y1: usize,
+        radius: f32,
+    ) -> Self {
+        let pad = (radius - 0.5).max(0.0).ceil() as usize;
+        Film::with_bounds(
+            width,
+            height,
+            x0.saturating_sub(pad),
+            y0.saturating_sub(pad),
+            x1 + pad,
+            y1 + pad,
+        )
+    }
+
+    // Raw pixels of the film's bounds, row by row with the buckets of each pixel together, for
+    // checkpoints.
+    pub fn pixels(&self) -> &[FilmPixel] {
+        &self.pixels
+    }
+
+    pub fn pixels_mut(&mut self) -> &mut [FilmPixel] {
+        &mut self.pixels
+    }
+
+    fn index(&self, x: usize, y: usize) -> Option<usize> {
+        if x < self.x0 || x >= self.x1 || y < self.y0 || y >= self.y1 {
+            return None;
+        }
+        Some((y - self.y0) * (self.x1 - self.x0) + (x - self.x0))
+    }
+
+    // Splats a sample taken at raster position (x, y) onto every pixel the filter covers.
+    pub fn add_sample(&mut self, x: f32, y: f32, value: Vec3, filter: &dyn Filter) {
+        self.add_bucket_sample(0, x, y, value, filter);
+    }
+
+    // Like `add_sample`, into the given bucket of each pixel.
+    pub fn add_bucket_sample(
+        &mut self,
+        bucket: usize,
+        x: f32,
+        y: f32,
+        value: Vec3,
+        filter: &dyn Filter,
+    ) {
+        let bucket = bucket % self.buckets;
+        let radius = filter.radius();
+        let px0 = (x - 0.5 - radius).ceil().max(self.x0 as f32) as usize;
+        let py0 = (y - 0.5 - radius).ceil().max(self.y0 as f32) as usize;
+        let px1 = (x - 0.5 + radius).floor().min(self.x1 as f32 - 1.0);
+        let py1 = (y - 0.5 + radius).floor().min(self.y1 as f32 - 1.0);
+        if px1 < 0.0 || py1 < 0.0 {
+            return;
+        }
+        for py in py0..=py1 as usize {
+            for px in px0..=px1 as usize {
+                let weight = filter.evaluate(px as f32 + 0.5 - x, py as f32 + 0.5 - y);
+                if weight == 0.0 {
+                    continue;
+                }
+                let i =
+                    ((py - self.y0) * (self.x1 - self.x0) + (px - self.x0)) * self.buckets + bucket;
+                self.pixels[i].sum += weight * value;
+                self.pixels[i].weight += weight;
+            }
+        }
+    }
+
+    // Adds the sums and weights of `other` where the two films overlap.
+    pub fn merge(&mut self, other: &Film) {
+        for y in other.y0.max(self.y0)..other.y1.min(self.y1) {
+            for x in other.x0.max(self.x0)..other.x1.min(self.x1) {
+                let (i, j) = match (self.index(x, y), other.index(x, y)) {
+                    (Some(i), Some(j)) => (i, j),
+                    _ => continue,
+                };
+                for b in 0..self.buckets.min(other.buckets) {
+                    let (i, j) = (i * self.buckets + b, j * other.buckets + b);
+                    self.pixels[i].sum += other.pixels[j].sum;
+                    self.pixels[i].weight += other.pixels[j].weight;
+                }
+            }
+        }
+    }
+
+    // With buckets, the sum is the median bucket mean scaled by the total weight.
+    pub fn pixel(&self, x: usize, y: usize) -> FilmPixel {
+        let i = match self.index(x, y) {
+            Some(i) => i * self.buckets,
+            None => {
+                return FilmPixel {
+                    sum: Vec3::new(0.0, 0.0, 0.0),
+                    weight: 0.0,
+                }
+            }
+        };
+        let buckets = &self.pixels[i..i + self.buckets];
+        if self.buckets == 1 {
+            return buckets[0];
+        }
+        let weight: f32 = buckets.iter().map(|b| b.weight).sum();
+        let mut means: Vec<Vec3> = buckets
+            .iter()
+            .filter(|b| b.weight > MIN_WEIGHT)
+            .map(|b| b.sum / b.weight)
+            .collect();
+        if means.is_empty() {
+            return FilmPixel {
+                sum: Vec3::new(0.0, 0.0, 0.0),
+                weight,
+            };
+        }
+        means.sort_by(|a, b| luminance(*a).total_cmp(&luminance(*b)));
+        let n = means.len();
+        let median = if n % 2 == 1 {
+            means[n / 2]
+        } else {
+            0.5 * (means[n / 2 - 1] + means[n / 2])
+        };
+        FilmPixel {
+            sum: median * weight,
+            weight,
+        }
+    }
+
+    // Reconstructed radiance, zero where no sample landed or the weights cancel out.
+    pub fn value(&self, x: usize, y: usize) -> Vec3 {
+        let p = self.pixel(x, y);
+        if p.weight <= MIN_WEIGHT {
+            Vec3::new(0.0, 0.0, 0.0)
+        } else {
+            p.sum / p.weight
+        }
+    }
+
+    // Reconstructed image as rows, top row first, in the layout `output::save` expects.
+    pub fn to_rows(&self) -> Vec<Vec<Vec3>> {
+        (0..self.height)
+            .map(|y| (0..self.width).map(|x| self.value(x, y)).collect())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::filter::{BoxFilter, Filter, FilterKind};
+    use super::*;
+
+    // Returns a fixed weight wherever it is evaluated.
+    struct ConstantFilter(f32);
+
+    impl Filter for ConstantFilter {
+        fn radius(&self) -> f32 {
+            0.5
+        }
+        fn evaluate(&self, _x: f32, _y: f32) -> f32 {
+            self.0
+        }
+    }
+
+    #[test]
+    fn box_filter_averages_samples_of_a_pixel() {
+        let mut film = Film::new(2, 1);
+        let filter = BoxFilter { radius: 0.5 };
+        film.add_sample(0.25, 0.5, Vec3::new(1.0, 2.0, 3.0), &filter);
+        film.add_sample(0.75, 0.5, Vec3::new(3.0, 2.0, 1.0), &filter);
+        let v = film.value(0, 0);
+        assert_eq!((v.r(), v.g(), v.b()), (2.0, 2.0, 2.0));
+        assert_eq!(film.pixel(1, 0).weight, 0.0);
+    }
+
+    #[test]
+    fn cancelled_weights_give_black() {
+        for weight in &[-0.5, 1e-6, 0.0] {
+            let mut film = Film::new(1, 1);
+            film.add_sample(0.5, 0.5, Vec3::new(1.0, 1.0, 1.0), &ConstantFilter(*weight));
+            let v = film.value(0, 0);
+            assert_eq!((v.r(), v.g(), v.b()), (0.0, 0.0, 0.0));
+        }
+    }
+
+    #[test]
+    fn negative_lobes_stay_finite() {
+        // A single sample in the negative lobe of a Mitchell filter.
+        let mut film = Film::new(4, 1);
+        let filter = FilterKind::Mitchell.create(FilterKind::Mitchell.default_radius());
+        film.add_sample(2.0, 0.5, Vec3::new(1.0, 1.0, 1.0), filter.as_ref());
+        for x in 0..4 {
+            let v = film.value(x, 0);
+            assert!(v.r().is_finite() && v.r() >= 0.0);
+        }
+    }
+
+    #[test]
+    fn median_of_means_ignores_an_outlier_bucket() {
+        let mut film = Film::new(1, 1).with_buckets(5);
+        let filter = BoxFilter { radius: 0.5 };
+        for bucket in 0..5 {
+            let value = if bucket == 3 {
+                1000.0
+            } else {
+                1.0 + bucket as f32 * 0.1
+            };
+            film.add_bucket_sample(bucket, 0.5, 0.5, Vec3::new(value, value, value), &filter);
+        }
+        let p = film.pixel(0, 0);
+        assert_eq!(p.weight, 5.0);
+        // The median of 1.0, 1.1, 1.2, 1.4 and 1000.
+        assert!((film.value(0, 0).g() - 1.2).abs() < 1e-5);
+        assert!((p.sum.g() - 1.2 * 5.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn even_bucket_counts_average_the_middle_means() {
+        let mut film = Film::new(1, 1).with_buckets(4);
+        let filter = BoxFilter { radius: 0.5 };
+        // Buckets with different weights: means 1, 2, 4 and 100 over weights 1, 3, 2 and 1.
+        let samples = [
+            (0, 1.0),
+            (1, 2.0),
+            (1, 2.0),
+            (1, 2.0),
+            (2, 4.0),
+            (2, 4.0),
+            (3, 100.0),
+        ];
+        for &(bucket, value) in &samples {
+            film.add_bucket_sample(bucket, 0.5, 0.5, Vec3::new(value, 0.0, 0.0), &filter);
+        }
+        let p = film.pixel(0, 0);
+        assert_eq!(p.weight, 7.0);
+        assert!((p.sum.r() - 0.5 * (2.0 + 4.0) * 7.0).abs() < 1e-4);
+        assert!((film.value(0, 0).r() - 3.0).abs() < 1e-5);
+    }
+}
@@ -0,0 +1,228 @@
+use std::f32::consts::PI;
+
+// Pixel reconstruction filter, evaluated at an offset in pixels from the pixel center.
+pub trait Filter: Sync + Send {
+    fn radius(&self) -> f32;
+    fn evaluate(&self, x: f32, y: f32) -> f32;
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FilterKind {
+    Box,
+    Tent,
+    Gaussian,
+    Mitchell,
+    Lanczos,
+}
+
+impl FilterKind {
+    pub const NAMES: &'static [&'static str] = &["box", "tent", "gaussian", "mitchell", "lanczos"];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "box" => Some(FilterKind::Box),
+            "tent" => Some(FilterKind::Tent),
+            "gaussian" => Some(FilterKind::Gaussian),
+            "mitchell" => Some(FilterKind::Mitchell),
+            "lanczos" => Some(FilterKind::Lanczos),
+            _ => None,
+        }
+    }
+
+    pub fn default_radius(self) -> f32 {
+        match self {
+            FilterKind::Box => 0.5,
+            FilterKind::Tent => 1.0,
+            FilterKind::Gaussian => 1.5,
+            FilterKind::Mitchell => 2.0,
+            FilterKind::Lanczos => 3.0,
+        }
+    }
+
+    pub fn create(self, radius: f32) -> Box<dyn Filter> {
+        match self {
+            FilterKind::Box => Box::new(BoxFilter { radius }),
+            FilterKind::Tent => Box::new(TentFilter { radius }),
+            FilterKind::Gaussian => Box::new(GaussianFilter::new(radius, 2.0)),
+            FilterKind::Mitchell => Box::new(MitchellFilter::new(radius, 1.0 / 3.0, 1.0 / 3.0)),
+            FilterKind::Lanczos => Box::new(LanczosFilter { radius }),
+        }
+    }
+}
+
+// A box of radius 0.5 averages the samples of each pixel on their own.
+pub struct BoxFilter {
+    pub radius: f32,
+}
+
+impl Filter for BoxFilter {
+    fn radius(&self) -> f32 {
+        self.radius
+    }
+    fn evaluate(&self, x: f32, y: f32) -> f32 {
+        // Half-open, so a sample on a pixel edge lands in exactly one pixel.
+        if x > -self.radius && x <= self.radius && y > -self.radius && y <= self.radius {
+            1.0
+        } else {
+            0.0
+        }
+    }
+}
+
+pub struct TentFilter {
+    pub radius: f32,
+}
+
+impl Filter for TentFilter {
+    fn radius(&self) -> f32 {
+        self.radius
+    }
+    fn evaluate(&self, x: f32, y: f32) -> f32 {
+        (self.radius - x.abs()).max(0.0) * (self.radius - y.abs()).max(0.0)
+    }
+}
+
+// Gaussian shifted down so it reaches zero at the radius.
+pub struct GaussianFilter {
+    radius: f32,
+    alpha: f32,
+    edge: f32,
+}
+
+impl GaussianFilter {
+    pub fn new(radius: f32, alpha: f32) -> Self {
+        GaussianFilter {
+            radius,
+            alpha,
+            edge: (-alpha * radius * radius).exp(),
+        }
+    }
+
+    fn gaussian(&self, d: f32) -> f32 {
+        ((-self.alpha * d * d).exp() - self.edge).max(0.0)
+    }
+}
+
+impl Filter for GaussianFilter {
+    fn radius(&self) -> f32 {
+        self.radius
+    }
+    fn evaluate(&self, x: f32, y: f32) -> f32 {
+        self.gaussian(x) * self.gaussian(y)
+    }
+}
+
+// Mitchell-Netravali cubic, stretched so its support [-2, 2] covers the radius.
+pub struct MitchellFilter {
+    radius: f32,
+    b: f32,
+    c: f32,
+}
+
+impl MitchellFilter {
+    pub fn new(radius: f32, b: f32, c: f32) -> Self {
+        MitchellFilter { radius, b, c }
+    }
+
+    fn mitchell(&self, x: f32) -> f32 {
+        let x = (2.0 * x / self.radius).abs();
+        let (b, c) = (self.b, self.c);
+        if x > 2.0 {
+            0.0
+        } else if x > 1.0 {
+            ((-b - 6.0 * c) * x * x * x
+                + (6.0 * b + 30.0 * c) * x * x
+                + (-12.0 * b - 48.0 * c) * x
+                + (8.0 * b + 24.0 * c))
+                / 6.0
+        } else {
+            ((12.0 - 9.0 * b - 6.0 * c) * x * x * x
+                + (-18.0 + 12.0 * b + 6.0 * c) * x * x
+                + (6.0 - 2.0 * b))
+                / 6.0
+        }
+    }
+}
+
+impl Filter for MitchellFilter {
+    fn radius(&self) -> f32 {
+        self.radius
+    }
+    fn evaluate(&self, x: f32, y: f32) -> f32 {
+        self.mitchell(x) * self.mitchell(y)
+    }
+}
+
+// Sinc windowed by a sinc stretched over the radius.
+pub struct LanczosFilter {
+    pub radius: f32,
+}
+
+impl LanczosFilter {
+    fn lanczos(&self, x: f32) -> f32 {
+        if x.abs() > self.radius {
+            return 0.0;
+        }
+        sinc(x) * sinc(x / self.radius)
+    }
+}
+
+impl Filter for LanczosFilter {
+    fn radius(&self) -> f32 {
+        self.radius
+    }
+    fn evaluate(&self, x: f32, y: f32) -> f32 {
+        self.lanczos(x) * self.lanczos(y)
+    }
+}
+
+fn sinc(x: f32) -> f32 {
+    let x = PI * x.abs();
+    if x < 1e-5 {
+        1.0
+    } else {
+        x.sin() / x
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn weights_vanish_beyond_the_radius() {
+        for name in FilterKind::NAMES {
+            let kind = FilterKind::from_name(name).unwrap();
+            let radius = kind.default_radius();
+            let filter = kind.create(radius);
+            assert!(filter.evaluate(0.0, 0.0) > 0.0, "{}", name);
+            for d in &[radius + 0.01, radius + 1.0] {
+                assert_eq!(filter.evaluate(*d, 0.0), 0.0, "{}", name);
+                assert_eq!(filter.evaluate(0.0, -*d), 0.0, "{}", name);
+            }
+        }
+    }
+
+    #[test]
+    fn only_mitchell_and_lanczos_go_negative() {
+        for name in FilterKind::NAMES {
+            let kind = FilterKind::from_name(name).unwrap();
+            let radius = kind.default_radius();
+            let filter = kind.create(radius);
+            let min = (0..=100)
+                .map(|i| filter.evaluate(radius * i as f32 / 100.0, 0.0))
+                .fold(f32::INFINITY, f32::min);
+            match kind {
+                FilterKind::Mitchell | FilterKind::Lanczos => assert!(min < 0.0, "{}", name),
+                _ => assert!(min >= 0.0, "{}", name),
+            }
+        }
+    }
+
+    #[test]
+    fn box_edges_are_half_open() {
+        let filter = BoxFilter { radius: 0.5 };
+        assert_eq!(filter.evaluate(0.5, 0.0), 1.0);
+        assert_eq!(filter.evaluate(-0.5, 0.0), 0.0);
+    }
+}
@@ -1,4 +1,5 @@
 use super::aabb::AABB;
+use super::light::Light;
 use super::material::Material;
 use super::ray::Ray;
 use super::vec::Vec3;
@@ -10,14 +11,39 @@ pub struct HitRecord {
     pub u: f32,
     pub v: f32,
     pub p: Vec3,
+    // Shading normal, which meshes may interpolate or take from the file.
     pub normal: Vec3,
+    // Normal of the surface that was actually hit.
+    pub geometric_normal: Vec3,
     pub material: Arc<dyn Material>,
 }
 
+// Bounding box and primitive intersection tests made while tracing a ray.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct TraversalStats {
+    pub aabb_tests: u32,
+    pub primitive_tests: u32,
+}
+
 pub trait Hitable: Sync + Send {
     fn hit(&self, r: Ray, t_min: f32, t_max: f32) -> Option<HitRecord>;
+    // Like `hit`, also counting the tests made on the way. Objects that contain others
+    // override it to pass the count down.
+    fn hit_counted(
+        &self,
+        r: Ray,
+        t_min: f32,
+        t_max: f32,
+        stats: &mut TraversalStats,
+    ) -> Option<HitRecord> {
+        stats.primitive_tests += 1;
+        self.hit(r, t_min, t_max)
+    }
     fn bounding_box(&self, t0: f32, t1: f32) -> Option<AABB>;
     fn required_bounding_box(&self, t0: f32, t1: f32) -> AABB {
         self.bounding_box(t0, t1).expect("No Bounding Box Found")
     }
+    fn collect_lights(&self, _lights: &mut Vec<Arc<dyn Light>>) {}
+    // Materials of the object in traversal order, duplicates included.
+    fn collect_materials(&self, _materials: &mut Vec<Arc<dyn Material>>) {}
 }
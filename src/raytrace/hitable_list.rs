@@ -1,7 +1,11 @@
 use super::aabb::AABB;
-use super::hitable::{HitRecord, Hitable};
+use super::hitable::{HitRecord, Hitable, TraversalStats};
+use super::light::Light;
+use super::material::Material;
 use super::ray::Ray;
 
+use std::sync::Arc;
+
 pub struct HitableList {
     pub objects: Vec<Box<dyn Hitable>>,
 }
@@ -15,21 +19,44 @@ impl HitableList {
     pub fn add(&mut self, sphere: Box<dyn Hitable>) {
         self.objects.push(sphere);
     }
-}
-
-impl Hitable for HitableList {
-    fn hit(&self, r: Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
-        let mut hit_anything: Option<HitRecord> = None;
+    // Closest hit together with the index of the object that was hit.
+    pub fn hit_with_index(&self, r: Ray, t_min: f32, t_max: f32) -> Option<(usize, HitRecord)> {
+        self.hit_with_index_counted(r, t_min, t_max, &mut TraversalStats::default())
+    }
+    pub fn hit_with_index_counted(
+        &self,
+        r: Ray,
+        t_min: f32,
+        t_max: f32,
+        stats: &mut TraversalStats,
+    ) -> Option<(usize, HitRecord)> {
+        let mut hit_anything: Option<(usize, HitRecord)> = None;
         let mut closest_so_far = t_max;
-        for i in &self.objects {
-            let temp_result = i.hit(r, t_min, closest_so_far);
+        for (index, i) in self.objects.iter().enumerate() {
+            let temp_result = i.hit_counted(r, t_min, closest_so_far, stats);
             if let Some(rec) = temp_result {
                 closest_so_far = rec.t;
-                hit_anything = Some(rec);
+                hit_anything = Some((index, rec));
             }
         }
         hit_anything
     }
+}
+
+impl Hitable for HitableList {
+    fn hit(&self, r: Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+        self.hit_with_index(r, t_min, t_max).map(|(_, rec)| rec)
+    }
+    fn hit_counted(
+        &self,
+        r: Ray,
+        t_min: f32,
+        t_max: f32,
+        stats: &mut TraversalStats,
+    ) -> Option<HitRecord> {
+        self.hit_with_index_counted(r, t_min, t_max, stats)
+            .map(|(_, rec)| rec)
+    }
     fn bounding_box(&self, t0: f32, t1: f32) -> Option<AABB> {
         if self.objects.is_empty() {
             return None;
@@ -49,4 +76,14 @@ impl Hitable for HitableList {
             None
         }
     }
+    fn collect_lights(&self, lights: &mut Vec<Arc<dyn Light>>) {
+        for object in &self.objects {
+            object.collect_lights(lights);
+        }
+    }
+    fn collect_materials(&self, materials: &mut Vec<Arc<dyn Material>>) {
+        for object in &self.objects {
+            object.collect_materials(materials);
+        }
+    }
 }
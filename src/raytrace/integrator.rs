@@ -0,0 +1,79 @@
+use super::rng::mix;
+use super::vec::Vec3;
+
+// What the renderer computes for each camera ray. Everything but `Path` is a debugging view of
+// the first hit, or of the work it took to find it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Integrator {
+    Path,
+    // Fraction of the hemisphere above the first hit that is unoccluded, see `AoSettings`.
+    AmbientOcclusion,
+    // Normals mapped from [-1, 1] to [0, 1] per axis.
+    ShadingNormal,
+    GeometricNormal,
+    // Camera depth, scaled so the farthest hit is white.
+    Depth,
+    // The hit record's u and v in red and green; barycentrics for triangles.
+    Uv,
+    // A distinct color per material.
+    MaterialId,
+    // False-color heatmaps of the tests made per camera ray.
+    AabbTests,
+    PrimitiveTests,
+}
+
+impl Integrator {
+    pub const NAMES: &'static [&'static str] = &[
+        "path",
+        "ao",
+        "shading-normal",
+        "geometric-normal",
+        "depth",
+        "uv",
+        "material-id",
+        "aabb-tests",
+        "primitive-tests",
+    ];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "path" => Some(Integrator::Path),
+            "ao" => Some(Integrator::AmbientOcclusion),
+            "shading-normal" => Some(Integrator::ShadingNormal),
+            "geometric-normal" => Some(Integrator::GeometricNormal),
+            "depth" => Some(Integrator::Depth),
+            "uv" => Some(Integrator::Uv),
+            "material-id" => Some(Integrator::MaterialId),
+            "aabb-tests" => Some(Integrator::AabbTests),
+            "primitive-tests" => Some(Integrator::PrimitiveTests),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct AoSettings {
+    // Cosine-weighted rays cast from each first hit.
+    pub samples: usize,
+    // Hits farther away than this do not occlude.
+    pub max_distance: f32,
+}
+
+impl Default for AoSettings {
+    fn default() -> Self {
+        AoSettings {
+            samples: 16,
+            max_distance: f32::MAX,
+        }
+    }
+}
+
+// Color of material `id`, black for zero. Neighbouring IDs get unrelated colors.
+pub fn id_color(id: u32) -> Vec3 {
+    if id == 0 {
+        return Vec3::new(0.0, 0.0, 0.0);
+    }
+    let h = mix(id as u64);
+    let channel = |shift: u32| 0.2 + 0.8 * ((h >> shift) & 0xff) as f32 / 255.0;
+    Vec3::new(channel(0), channel(8), channel(16))
+}
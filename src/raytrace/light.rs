@@ -0,0 +1,205 @@
+use super::hitable::Hitable;
+use super::vec::Vec3;
+
+use std::f32::consts::PI;
+use std::sync::Arc;
+
+pub trait Light: Sync + Send {
+    // Solid angle density of `random` producing `direction` from `origin`, zero if it misses.
+    fn pdf_value(&self, origin: Vec3, direction: Vec3) -> f32;
+    // Direction from `origin` towards a point on the light chosen by the uniform sample `u`.
+    fn random(&self, origin: Vec3, u: (f32, f32)) -> Vec3;
+}
+
+pub struct LightList {
+    pub lights: Vec<Arc<dyn Light>>,
+}
+
+impl LightList {
+    pub fn from_world(world: &dyn Hitable) -> Self {
+        let mut lights = vec![];
+        world.collect_lights(&mut lights);
+        LightList { lights }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lights.is_empty()
+    }
+}
+
+impl Light for LightList {
+    fn pdf_value(&self, origin: Vec3, direction: Vec3) -> f32 {
+        if self.lights.is_empty() {
+            return 0.0;
+        }
+        let sum: f32 = self
+            .lights
+            .iter()
+            .map(|l| l.pdf_value(origin, direction))
+            .sum();
+        sum / self.lights.len() as f32
+    }
+
+    fn random(&self, origin: Vec3, u: (f32, f32)) -> Vec3 {
+        // The first coordinate picks the light and is then stretched back to [0, 1).
+        let n = self.lights.len();
+        let scaled = u.0 * n as f32;
+        let index = (scaled as usize).min(n - 1);
+        let u0 = (scaled - index as f32).clamp(0.0, 1.0 - f32::EPSILON);
+        self.lights[index].random(origin, (u0, u.1))
+    }
+}
+
+// Converts an area density at a point seen at distance `t` along `direction` to solid angle.
+pub fn area_to_solid_angle(area: f32, t: f32, direction: Vec3, normal: Vec3) -> f32 {
+    let distance_squared = t * t * direction.squared_length();
+    let cosine = (Vec3::dot(&direction, &normal) / (direction.length() * normal.length())).abs();
+    if cosine <= 0.0 || area <= 0.0 {
+        return 0.0;
+    }
+    distance_squared / (cosine * area)
+}
+
+// Returns a direction inside the cone around +z with the given half-angle cosine.
+pub fn random_in_cone(cos_theta_max: f32, u: (f32, f32)) -> Vec3 {
+    let z = 1.0 + u.0 * (cos_theta_max - 1.0);
+    let r = f32::sqrt((1.0 - z * z).max(0.0));
+    let phi = 2.0 * PI * u.1;
+    Vec3::new(r * phi.cos(), r * phi.sin(), z)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::material::DiffuseLight;
+    use super::super::rectangle::XY;
+    use super::super::rng::Rng;
+    use super::super::sphere::Sphere;
+    use super::super::texture::ConstantTexture;
+    use super::super::triangle::Triangle;
+    use super::*;
+
+    fn lights() -> Vec<(&'static str, Arc<dyn Light>)> {
+        let emit = || {
+            Arc::new(DiffuseLight::new(Box::new(ConstantTexture::new(
+                Vec3::new(4.0, 4.0, 4.0),
+            ))))
+        };
+        let sphere: Arc<dyn Light> = Arc::new(Sphere::new(Vec3::new(0.5, 1.0, -2.0), 0.8, emit()));
+        let rect: Arc<dyn Light> = Arc::new(XY::new(-1.0, 0.5, -0.5, 1.0, 1.0, emit()));
+        let triangle: Arc<dyn Light> = Arc::new(Triangle::new(
+            Vec3::new(-1.0, -1.0, -1.0),
+            Vec3::new(1.0, -1.0, -1.5),
+            Vec3::new(0.0, 1.0, -1.0),
+            emit(),
+        ));
+        vec![
+            ("sphere", Arc::clone(&sphere)),
+            (
+                "surrounding sphere",
+                Arc::new(Sphere::new(Vec3::new(0.2, -0.1, 0.3), 2.0, emit())),
+            ),
+            ("rectangle", Arc::clone(&rect)),
+            ("triangle", Arc::clone(&triangle)),
+            (
+                "list",
+                Arc::new(LightList {
+                    lights: vec![sphere, rect, triangle],
+                }),
+            ),
+        ]
+    }
+
+    fn uniform_sphere(rng: &mut Rng) -> Vec3 {
+        let z = 1.0 - 2.0 * rng.drand48();
+        let r = (1.0 - z * z).max(0.0).sqrt();
+        let phi = 2.0 * PI * rng.drand48();
+        Vec3::new(r * phi.cos(), r * phi.sin(), z)
+    }
+
+    // Directions grazing a sphere's rim can miss it and get no density, which the renderer
+    // drops; they must stay rare.
+    #[test]
+    fn sampled_directions_have_a_density() {
+        let origin = Vec3::new(0.0, 0.0, 0.0);
+        let mut rng = Rng::new(1);
+        let n = 10_000;
+        for (name, light) in lights() {
+            let mut missed = 0;
+            for _ in 0..n {
+                let direction = light.random(origin, (rng.drand48(), rng.drand48()));
+                let pdf = light.pdf_value(origin, direction);
+                assert!(pdf.is_finite() && pdf >= 0.0, "{} {:?}", name, direction);
+                if pdf == 0.0 {
+                    missed += 1;
+                }
+            }
+            assert!(
+                missed < n / 1000,
+                "{}: {} samples without density",
+                name,
+                missed
+            );
+        }
+    }
+
+    #[test]
+    fn pdf_integrates_to_one_over_the_sphere() {
+        let origin = Vec3::new(0.0, 0.0, 0.0);
+        let mut rng = Rng::new(2);
+        let n = 200_000;
+        for (name, light) in lights() {
+            let sum: f64 = (0..n)
+                .map(|_| light.pdf_value(origin, uniform_sphere(&mut rng)) as f64)
+                .sum();
+            let integral = sum / n as f64 * 4.0 * std::f64::consts::PI;
+            assert!((integral - 1.0).abs() < 0.03, "{} {}", name, integral);
+        }
+    }
+
+    // Integrating over each light by its own samples must match integrating over the sphere,
+    // which holds only if `pdf_value` is the density `random` actually draws from.
+    #[test]
+    fn sampling_agrees_with_uniform_sampling() {
+        let origin = Vec3::new(0.0, 0.0, 0.0);
+        let f = |d: Vec3| {
+            let d = d.normalize();
+            1.5 + d.x() + d.y() * d.z()
+        };
+        let mut rng = Rng::new(3);
+        let n = 200_000;
+        for (name, light) in lights() {
+            let uniform = (0..n)
+                .map(|_| {
+                    let d = uniform_sphere(&mut rng);
+                    if light.pdf_value(origin, d) > 0.0 {
+                        f(d) as f64
+                    } else {
+                        0.0
+                    }
+                })
+                .sum::<f64>()
+                / n as f64
+                * 4.0
+                * std::f64::consts::PI;
+            let sampled = (0..n)
+                .map(|_| {
+                    let d = light.random(origin, (rng.drand48(), rng.drand48()));
+                    let pdf = light.pdf_value(origin, d);
+                    if pdf > 0.0 {
+                        (f(d) / pdf) as f64
+                    } else {
+                        0.0
+                    }
+                })
+                .sum::<f64>()
+                / n as f64;
+            assert!(
+                (sampled / uniform - 1.0).abs() < 0.03,
+                "{} {} {}",
+                name,
+                sampled,
+                uniform
+            );
+        }
+    }
+}
@@ -1,16 +1,57 @@
 use super::hitable::HitRecord;
 use super::ray::Ray;
 use super::texture::Texture;
-use super::vec::{drand48, random_in_unit_sphere, Vec3};
+use super::vec::{onb, random_cosine_direction, random_in_unit_sphere, random_unit_vector, Vec3};
 
+use std::f32::consts::PI;
 use std::sync::Arc;
 
+pub struct ScatterRecord {
+    pub ray: Ray,
+    // BSDF times cosine over pdf, the factor applied to the radiance carried by `ray`.
+    pub attenuation: Vec3,
+    // Solid angle density of `ray`, zero when `is_specular`.
+    pub pdf: f32,
+    pub is_specular: bool,
+}
+
 pub trait Material: Sync + Send {
-    fn scatter(&self, _r_in: &Ray, _rec: &HitRecord) -> Option<(Ray, Vec3)> {
+    // `uc` and `u` are uniform samples, for choosing a lobe and a direction within it.
+    fn sample(
+        &self,
+        _r_in: &Ray,
+        _rec: &HitRecord,
+        _uc: f32,
+        _u: (f32, f32),
+    ) -> Option<ScatterRecord> {
         None
     }
-    fn emitted(&self, _u: f32, _v: f32, _p: Vec3) -> (Vec3, Vec3) {
-        (Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, 0.0))
+    // BSDF times cosine for light leaving along `direction`, zero for specular materials.
+    fn eval(&self, _r_in: &Ray, _rec: &HitRecord, _direction: Vec3) -> Vec3 {
+        Vec3::new(0.0, 0.0, 0.0)
+    }
+    fn pdf(&self, _r_in: &Ray, _rec: &HitRecord, _direction: Vec3) -> f32 {
+        0.0
+    }
+    fn emitted(&self, _u: f32, _v: f32, _p: Vec3) -> Vec3 {
+        Vec3::new(0.0, 0.0, 0.0)
+    }
+    fn is_emissive(&self) -> bool {
+        false
+    }
+    // Reflectance at `rec` for the albedo AOV.
+    fn albedo(&self, _rec: &HitRecord) -> Vec3 {
+        Vec3::new(0.0, 0.0, 0.0)
+    }
+}
+
+// Shading normal flipped to the side the ray arrives from.
+fn facing_normal(r_in: &Ray, rec: &HitRecord) -> Vec3 {
+    let n = rec.normal.normalize();
+    if Vec3::dot(&r_in.direction(), &n) > 0.0 {
+        -n
+    } else {
+        n
     }
 }
 
@@ -25,11 +66,36 @@ impl Lambertian {
 }
 
 impl Material for Lambertian {
-    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<(Ray, Vec3)> {
-        let target: Vec3 = rec.p + rec.normal + random_in_unit_sphere();
-        let scattered = Ray::new(rec.p, target - rec.p, r_in.time());
-        let attenuation = self.albedo.value(rec.u, rec.v, rec.p);
-        Some((scattered, attenuation))
+    fn sample(
+        &self,
+        r_in: &Ray,
+        rec: &HitRecord,
+        _uc: f32,
+        u: (f32, f32),
+    ) -> Option<ScatterRecord> {
+        let d = random_cosine_direction(u);
+        let (u, v, w) = onb(facing_normal(r_in, rec));
+        let direction = d.x() * u + d.y() * v + d.z() * w;
+        let pdf = d.z() / PI;
+        if pdf <= 0.0 {
+            return None;
+        }
+        Some(ScatterRecord {
+            ray: Ray::new(rec.p, direction, r_in.time()),
+            attenuation: self.albedo.value(rec.u, rec.v, rec.p),
+            pdf,
+            is_specular: false,
+        })
+    }
+    fn eval(&self, r_in: &Ray, rec: &HitRecord, direction: Vec3) -> Vec3 {
+        self.albedo.value(rec.u, rec.v, rec.p) * self.pdf(r_in, rec, direction)
+    }
+    fn pdf(&self, r_in: &Ray, rec: &HitRecord, direction: Vec3) -> f32 {
+        let cosine = Vec3::dot(&facing_normal(r_in, rec), &direction.normalize());
+        cosine.max(0.0) / PI
+    }
+    fn albedo(&self, rec: &HitRecord) -> Vec3 {
+        self.albedo.value(rec.u, rec.v, rec.p)
     }
 }
 
@@ -48,20 +114,28 @@ impl Metal {
 }
 
 impl Material for Metal {
-    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<(Ray, Vec3)> {
-        let reflected: Vec3 = reflect(Vec3::unit_vector(r_in.direction()), rec.normal);
+    fn sample(&self, r_in: &Ray, rec: &HitRecord, uc: f32, u: (f32, f32)) -> Option<ScatterRecord> {
+        let normal = rec.normal.normalize();
+        let reflected: Vec3 = reflect(Vec3::unit_vector(r_in.direction()), normal);
         let scattered = Ray::new(
             rec.p,
-            reflected + self.roughness * random_in_unit_sphere(),
+            reflected + self.roughness * random_in_unit_sphere(u, uc),
             r_in.time(),
         );
-        let attenuation = self.albedo;
-        if Vec3::dot(&scattered.direction(), &rec.normal) > 0.0 {
-            Some((scattered, attenuation))
+        if Vec3::dot(&scattered.direction(), &normal) > 0.0 {
+            Some(ScatterRecord {
+                ray: scattered,
+                attenuation: self.albedo,
+                pdf: 0.0,
+                is_specular: true,
+            })
         } else {
             None
         }
     }
+    fn albedo(&self, _rec: &HitRecord) -> Vec3 {
+        self.albedo
+    }
 }
 
 pub struct Dielectric {
@@ -75,57 +149,74 @@ impl Dielectric {
 }
 
 impl Material for Dielectric {
-    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<(Ray, Vec3)> {
+    fn sample(
+        &self,
+        r_in: &Ray,
+        rec: &HitRecord,
+        uc: f32,
+        _u: (f32, f32),
+    ) -> Option<ScatterRecord> {
+        let normal = rec.normal.normalize();
         let outward_normal: Vec3;
-        let reflected: Vec3 = reflect(r_in.direction(), rec.normal);
+        let reflected: Vec3 = reflect(r_in.direction(), normal);
         let ni_over_nt: f32;
-        let attenuation = Vec3::new(1.0, 1.0, 1.0);
         let mut refracted: Vec3 = Vec3::new(0.0, 0.0, 0.0);
-        let reflect_prob: f32;
         let mut cosine: f32;
-        let scattered: Ray;
 
-        if Vec3::dot(&r_in.direction(), &rec.normal) > 0.0 {
-            outward_normal = -rec.normal;
+        if Vec3::dot(&r_in.direction(), &normal) > 0.0 {
+            outward_normal = -normal;
             ni_over_nt = self.ref_indx;
-            cosine = Vec3::dot(&r_in.direction(), &rec.normal) / r_in.direction().length();
+            cosine = Vec3::dot(&r_in.direction(), &normal) / r_in.direction().length();
             cosine = f32::sqrt(1.0 - self.ref_indx * self.ref_indx * (1.0 - cosine * cosine));
         } else {
-            outward_normal = rec.normal;
+            outward_normal = normal;
             ni_over_nt = 1.0 / self.ref_indx;
-            cosine = -Vec3::dot(&r_in.direction(), &rec.normal) / r_in.direction().length();
+            cosine = -Vec3::dot(&r_in.direction(), &normal) / r_in.direction().length();
         }
-        if refract(r_in.direction(), outward_normal, ni_over_nt, &mut refracted) {
-            reflect_prob = schlick(cosine, self.ref_indx);
+        let can_refract = refract(r_in.direction(), outward_normal, ni_over_nt, &mut refracted);
+        let reflect_prob = if can_refract {
+            schlick(cosine, self.ref_indx)
         } else {
-            reflect_prob = 1.0;
-        }
-        if drand48() < reflect_prob {
-            scattered = Ray::new(rec.p, reflected + Vec3::new(0.5, 0.5, 0.5), r_in.time());
+            1.0
+        };
+        let scattered = if uc < reflect_prob {
+            Ray::new(rec.p, reflected, r_in.time())
         } else {
-            scattered = Ray::new(rec.p, refracted, r_in.time());
-        }
-        Some((scattered, attenuation))
+            Ray::new(rec.p, refracted, r_in.time())
+        };
+        Some(ScatterRecord {
+            ray: scattered,
+            attenuation: Vec3::new(1.0, 1.0, 1.0),
+            pdf: 0.0,
+            is_specular: true,
+        })
+    }
+    fn albedo(&self, _rec: &HitRecord) -> Vec3 {
+        Vec3::new(1.0, 1.0, 1.0)
     }
 }
 
 pub struct DiffuseLight {
     pub emit: Box<dyn Texture>,
-    pub pos: Vec3,
 }
 
 impl DiffuseLight {
-    pub fn new(emit: Box<dyn Texture>, pos: Vec3) -> Self {
-        DiffuseLight { emit, pos }
+    pub fn new(emit: Box<dyn Texture>) -> Self {
+        DiffuseLight { emit }
     }
 }
 impl Material for DiffuseLight {
-    fn scatter(&self, _r_in: &Ray, _rec: &HitRecord) -> Option<(Ray, Vec3)> {
-        None
+    fn emitted(&self, u: f32, v: f32, p: Vec3) -> Vec3 {
+        self.emit.value(u, v, p)
     }
 
-    fn emitted(&self, u: f32, v: f32, p: Vec3) -> (Vec3, Vec3) {
-        (self.emit.value(u, v, p), self.pos)
+    fn is_emissive(&self) -> bool {
+        true
+    }
+    // Emission clamped to a displayable range, as denoisers expect for lights.
+    fn albedo(&self, rec: &HitRecord) -> Vec3 {
+        let e = self.emit.value(rec.u, rec.v, rec.p);
+        Vec3::new(e.r().min(1.0), e.g().min(1.0), e.b().min(1.0))
     }
 }
 
@@ -140,10 +231,28 @@ impl Isotropic {
 }
 
 impl Material for Isotropic {
-    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<(Ray, Vec3)> {
-        let scattered = Ray::new(rec.p, random_in_unit_sphere(), r_in.time());
-        let attenuation = self.texture.value(rec.u, rec.v, rec.p);
-        Some((scattered, attenuation))
+    fn sample(
+        &self,
+        r_in: &Ray,
+        rec: &HitRecord,
+        _uc: f32,
+        u: (f32, f32),
+    ) -> Option<ScatterRecord> {
+        Some(ScatterRecord {
+            ray: Ray::new(rec.p, random_unit_vector(u), r_in.time()),
+            attenuation: self.texture.value(rec.u, rec.v, rec.p),
+            pdf: 1.0 / (4.0 * PI),
+            is_specular: false,
+        })
+    }
+    fn eval(&self, _r_in: &Ray, rec: &HitRecord, _direction: Vec3) -> Vec3 {
+        self.texture.value(rec.u, rec.v, rec.p) / (4.0 * PI)
+    }
+    fn pdf(&self, _r_in: &Ray, _rec: &HitRecord, _direction: Vec3) -> f32 {
+        1.0 / (4.0 * PI)
+    }
+    fn albedo(&self, rec: &HitRecord) -> Vec3 {
+        self.texture.value(rec.u, rec.v, rec.p)
     }
 }
 
@@ -168,3 +277,105 @@ pub fn refract(v: Vec3, n: Vec3, ni_over_nt: f32, refracted: &mut Vec3) -> bool
         false
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::super::rng::Rng;
+    use super::super::texture::ConstantTexture;
+    use super::*;
+
+    fn hit(material: Arc<dyn Material>) -> (Ray, HitRecord) {
+        let normal = Vec3::new(0.3, 1.0, -0.2).normalize();
+        let rec = HitRecord {
+            t: 1.0,
+            u: 0.5,
+            v: 0.5,
+            p: Vec3::new(0.0, 0.0, 0.0),
+            normal,
+            geometric_normal: normal,
+            material,
+        };
+        (
+            Ray::new(Vec3::new(1.0, 2.0, 0.5), Vec3::new(-1.0, -2.0, -0.5), 0.0),
+            rec,
+        )
+    }
+
+    fn diffuse() -> Vec<(&'static str, Arc<dyn Material>)> {
+        let albedo = || Box::new(ConstantTexture::new(Vec3::new(0.8, 0.5, 0.2)));
+        vec![
+            ("lambertian", Arc::new(Lambertian::new(albedo()))),
+            ("isotropic", Isotropic::new(albedo())),
+        ]
+    }
+
+    fn uniform_sphere(rng: &mut Rng) -> Vec3 {
+        let z = 1.0 - 2.0 * rng.drand48();
+        let r = (1.0 - z * z).max(0.0).sqrt();
+        let phi = 2.0 * PI * rng.drand48();
+        Vec3::new(r * phi.cos(), r * phi.sin(), z)
+    }
+
+    fn close(a: Vec3, b: Vec3) -> bool {
+        (a - b).length() < 1e-4 * (1.0 + b.length())
+    }
+
+    #[test]
+    fn samples_report_the_pdf_and_eval_of_their_direction() {
+        let mut rng = Rng::new(4);
+        for (name, material) in diffuse() {
+            let (ray, rec) = hit(Arc::clone(&material));
+            for _ in 0..10_000 {
+                let u = (rng.drand48(), rng.drand48());
+                let srec = match material.sample(&ray, &rec, rng.drand48(), u) {
+                    Some(srec) => srec,
+                    None => continue,
+                };
+                let direction = srec.ray.direction();
+                let pdf = material.pdf(&ray, &rec, direction);
+                assert!(!srec.is_specular, "{}", name);
+                assert!(
+                    (srec.pdf - pdf).abs() < 1e-4 * pdf,
+                    "{} {} {}",
+                    name,
+                    srec.pdf,
+                    pdf
+                );
+                let eval = material.eval(&ray, &rec, direction);
+                assert!(close(srec.attenuation, eval / pdf), "{} {:?}", name, eval);
+            }
+        }
+    }
+
+    #[test]
+    fn pdf_integrates_to_one_over_the_sphere() {
+        let mut rng = Rng::new(5);
+        let n = 200_000;
+        for (name, material) in diffuse() {
+            let (ray, rec) = hit(Arc::clone(&material));
+            let sum: f64 = (0..n)
+                .map(|_| material.pdf(&ray, &rec, uniform_sphere(&mut rng)) as f64)
+                .sum();
+            let integral = sum / n as f64 * 4.0 * std::f64::consts::PI;
+            assert!((integral - 1.0).abs() < 0.02, "{} {}", name, integral);
+        }
+    }
+
+    // Light sampling must not be combined with mirror and glass directions.
+    #[test]
+    fn specular_materials_have_no_density() {
+        let specular: Vec<Arc<dyn Material>> = vec![
+            Arc::new(Metal::new(Vec3::new(0.9, 0.9, 0.9), 0.0)),
+            Arc::new(Dielectric::new(1.5)),
+        ];
+        for material in specular {
+            let (ray, rec) = hit(Arc::clone(&material));
+            let srec = material.sample(&ray, &rec, 0.5, (0.5, 0.5)).unwrap();
+            assert!(srec.is_specular);
+            let direction = srec.ray.direction();
+            assert_eq!(material.pdf(&ray, &rec, direction), 0.0);
+            let eval = material.eval(&ray, &rec, direction);
+            assert_eq!((eval.r(), eval.g(), eval.b()), (0.0, 0.0, 0.0));
+        }
+    }
+}
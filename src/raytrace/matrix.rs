@@ -7,7 +7,7 @@ pub struct Matrix44 {
 }
 
 impl Matrix44 {
-    #[cfg_attr(rustfmt, rustfmt_skip)]
+    #[rustfmt::skip]
     pub fn identity() -> Matrix44 {
         Matrix44 {
             elements: [[1.0, 0.0, 0.0, 0.0],
@@ -21,7 +21,7 @@ impl Matrix44 {
         Matrix44::scale(s, s, s)
     }
 
-    #[cfg_attr(rustfmt, rustfmt_skip)]
+    #[rustfmt::skip]
     pub fn scale(sx: f32, sy: f32, sz: f32) -> Matrix44 {
         Matrix44 {
             elements: [[sx, 0.0, 0.0, 0.0],
@@ -31,7 +31,7 @@ impl Matrix44 {
         }
     }
 
-    #[cfg_attr(rustfmt, rustfmt_skip)]
+    #[rustfmt::skip]
     pub fn rotate_x(t: f32) -> Matrix44 {
         let sin = t.sin();
         let cos = t.cos();
@@ -43,7 +43,7 @@ impl Matrix44 {
         }
     }
 
-    #[cfg_attr(rustfmt, rustfmt_skip)]
+    #[rustfmt::skip]
     pub fn rotate_y(t: f32) -> Matrix44 {
         let sin = t.sin();
         let cos = t.cos();
@@ -55,7 +55,7 @@ impl Matrix44 {
         }
     }
 
-    #[cfg_attr(rustfmt, rustfmt_skip)]
+    #[rustfmt::skip]
     pub fn rotate_z(t: f32) -> Matrix44 {
         let sin = t.sin();
         let cos = t.cos();
@@ -67,7 +67,7 @@ impl Matrix44 {
         }
     }
 
-    #[cfg_attr(rustfmt, rustfmt_skip)]
+    #[rustfmt::skip]
     pub fn translate(tx: f32, ty: f32, tz: f32) -> Matrix44 {
         Matrix44 {
             elements: [[1.0, 0.0, 0.0, tx],
@@ -162,7 +162,7 @@ impl IndexMut<usize> for Matrix44 {
 impl Mul for Matrix44 {
     type Output = Matrix44;
 
-    #[cfg_attr(rustfmt, rustfmt_skip)]
+    #[rustfmt::skip]
     fn mul(self, other: Matrix44) -> Matrix44 {
         let mut result = Matrix44::identity();
         for i in 0..4 {
@@ -38,14 +38,13 @@ pub fn hitable_mesh(path: &Path, matrix: Matrix44, material: Arc<dyn Material>)
                 mesh.positions[i2 * 3 + 2],
             );
 
-            let tri: Triangle;
-            if !mesh.normals.is_empty() {
+            let tri = if !mesh.normals.is_empty() {
                 let normal = Vec3::new(
                     mesh.normals[i0 * 3],
                     mesh.normals[i0 * 3 + 1],
                     mesh.normals[i0 * 3 + 2],
                 );
-                tri = Triangle::new_normal(
+                Triangle::new_normal(
                     matrix * v0,
                     matrix * v1,
                     matrix * v2,
@@ -53,8 +52,8 @@ pub fn hitable_mesh(path: &Path, matrix: Matrix44, material: Arc<dyn Material>)
                     Arc::clone(&material),
                 )
             } else {
-                tri = Triangle::new(matrix * v0, matrix * v1, matrix * v2, Arc::clone(&material));
-            }
+                Triangle::new(matrix * v0, matrix * v1, matrix * v2, Arc::clone(&material))
+            };
 
             world.push(Box::new(tri));
         }
@@ -1,19 +1,37 @@
 pub mod aabb;
+pub mod adaptive;
+pub mod aov;
 pub mod bvh;
 pub mod camera;
+pub mod checkpoint;
 pub mod constant_medium;
 pub mod cube;
+pub mod denoise;
+pub mod distribution;
+pub mod environment;
+pub mod exr;
+pub mod film;
+pub mod filter;
 pub mod hitable;
 pub mod hitable_list;
+pub mod integrator;
+pub mod light;
 pub mod material;
 pub mod matrix;
 pub mod mesh;
 pub mod moving_sphere;
+pub mod output;
 pub mod perlin;
 pub mod ray;
 pub mod rectangle;
+pub mod renderer;
+pub mod rng;
+pub mod sampler;
+pub mod sky;
 pub mod skymap;
 pub mod sphere;
 pub mod texture;
+pub mod tiles;
+pub mod tonemap;
 pub mod triangle;
 pub mod vec;
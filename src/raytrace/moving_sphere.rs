@@ -57,6 +57,7 @@ impl Hitable for Movingsphere {
                     v,
                     p,
                     normal,
+                    geometric_normal: normal,
                     material: self.material.clone(),
                 });
             }
@@ -72,6 +73,7 @@ impl Hitable for Movingsphere {
                     v,
                     p,
                     normal,
+                    geometric_normal: normal,
                     material: self.material.clone(),
                 });
             }
@@ -93,4 +95,7 @@ impl Hitable for Movingsphere {
             max: sbox.max,
         })
     }
+    fn collect_materials(&self, materials: &mut Vec<Arc<dyn Material>>) {
+        materials.push(Arc::clone(&self.material));
+    }
 }
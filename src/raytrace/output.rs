@@ -0,0 +1,128 @@
+extern crate image;
+
+use super::tonemap::ToneMapping;
+use super::vec::Vec3;
+
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ImageFormat {
+    Png,
+    Ppm,
+    Hdr,
+    Pfm,
+}
+
+impl ImageFormat {
+    pub fn from_path(path: &Path) -> io::Result<Self> {
+        let extension = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(|e| e.to_ascii_lowercase());
+        match extension.as_deref() {
+            Some("png") => Ok(ImageFormat::Png),
+            Some("ppm") => Ok(ImageFormat::Ppm),
+            Some("hdr") => Ok(ImageFormat::Hdr),
+            Some("pfm") => Ok(ImageFormat::Pfm),
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "unsupported output format for '{}', expected .png, .ppm, .hdr or .pfm",
+                    path.display()
+                ),
+            )),
+        }
+    }
+}
+
+// PNG and PPM are tone mapped and sRGB encoded; HDR and PFM keep the linear values untouched.
+pub fn save(path: &Path, rows: &[Vec<Vec3>], tone_mapping: &ToneMapping) -> io::Result<()> {
+    let height = rows.len();
+    let width = rows.first().map_or(0, |r| r.len());
+    match ImageFormat::from_path(path)? {
+        ImageFormat::Png => image::save_buffer(
+            path,
+            &to_rgb8(rows, tone_mapping),
+            width as u32,
+            height as u32,
+            image::RGB(8),
+        ),
+        ImageFormat::Ppm => {
+            let mut out = BufWriter::new(File::create(path)?);
+            write!(out, "P6\n{} {}\n255\n", width, height)?;
+            out.write_all(&to_rgb8(rows, tone_mapping))?;
+            out.flush()
+        }
+        ImageFormat::Hdr => {
+            let pixels: Vec<image::Rgb<f32>> = rows
+                .iter()
+                .flatten()
+                .map(|c| image::Rgb([c.r(), c.g(), c.b()]))
+                .collect();
+            let out = BufWriter::new(File::create(path)?);
+            image::hdr::HDREncoder::new(out).encode(&pixels, width, height)
+        }
+        ImageFormat::Pfm => {
+            // Little-endian floats, bottom row first. Unlike HDR it keeps negative values,
+            // which normals and positions need.
+            let mut out = BufWriter::new(File::create(path)?);
+            write!(out, "PF\n{} {}\n-1.0\n", width, height)?;
+            for c in rows.iter().rev().flatten() {
+                for v in &[c.r(), c.g(), c.b()] {
+                    out.write_all(&v.to_le_bytes())?;
+                }
+            }
+            out.flush()
+        }
+    }
+}
+
+// False-color rows for per-pixel statistics, from black through blue, red and yellow to white
+// at the largest value.
+pub fn heatmap(values: &[Vec<f32>]) -> Vec<Vec<Vec3>> {
+    let max = values
+        .iter()
+        .flatten()
+        .fold(0.0f32, |m, v| m.max(*v))
+        .max(f32::MIN_POSITIVE);
+    let stops = [
+        Vec3::new(0.0, 0.0, 0.0),
+        Vec3::new(0.0, 0.0, 1.0),
+        Vec3::new(1.0, 0.0, 0.0),
+        Vec3::new(1.0, 1.0, 0.0),
+        Vec3::new(1.0, 1.0, 1.0),
+    ];
+    let color = |v: f32| {
+        let t = (v / max).clamp(0.0, 1.0) * (stops.len() - 1) as f32;
+        let i = (t as usize).min(stops.len() - 2);
+        let f = t - i as f32;
+        (1.0 - f) * stops[i] + f * stops[i + 1]
+    };
+    values
+        .iter()
+        .map(|row| row.iter().map(|v| color(*v)).collect())
+        .collect()
+}
+
+// Path of an AOV pass next to `path`, e.g. render.png -> render_normal.pfm.
+pub fn aov_path(path: &Path, name: &str) -> PathBuf {
+    pass_path(path, name, "pfm")
+}
+
+// Path of an extra pass next to `path` with the given extension.
+pub fn pass_path(path: &Path, name: &str, extension: &str) -> PathBuf {
+    let stem = path
+        .file_stem()
+        .map_or(String::from("render"), |s| s.to_string_lossy().into_owned());
+    path.with_file_name(format!("{}_{}.{}", stem, name, extension))
+}
+
+fn to_rgb8(rows: &[Vec<Vec3>], tone_mapping: &ToneMapping) -> Vec<u8> {
+    let mut buf = Vec::with_capacity(rows.len() * rows.first().map_or(0, |r| r.len()) * 3);
+    for c in rows.iter().flatten() {
+        buf.extend_from_slice(&tone_mapping.encode(*c));
+    }
+    buf
+}
@@ -1,4 +1,5 @@
-use super::vec::{drand48, Vec3};
+use super::rng::Rng;
+use super::vec::Vec3;
 
 #[derive(Default)]
 pub struct Perlin {
@@ -9,12 +10,12 @@ pub struct Perlin {
 }
 
 impl Perlin {
-    pub fn new() -> Self {
+    pub fn new(rng: &mut Rng) -> Self {
         Perlin {
-            perm_x: Perlin::perlin_generate_perm(),
-            perm_y: Perlin::perlin_generate_perm(),
-            perm_z: Perlin::perlin_generate_perm(),
-            ran_vec: Perlin::perlin_generate(),
+            perm_x: Perlin::perlin_generate_perm(rng),
+            perm_y: Perlin::perlin_generate_perm(rng),
+            perm_z: Perlin::perlin_generate_perm(rng),
+            ran_vec: Perlin::perlin_generate(rng),
         }
     }
 
@@ -32,10 +33,9 @@ impl Perlin {
         for di in 0..2 {
             for dj in 0..2 {
                 for dk in 0..2 {
-                    let c = self.ran_vec[(self.perm_x[((i + di) & 255) as usize]
+                    let c = self.ran_vec[self.perm_x[((i + di) & 255) as usize]
                         ^ self.perm_y[((j + dj) & 255) as usize]
-                        ^ self.perm_z[((k + dk) & 255) as usize])
-                        as usize];
+                        ^ self.perm_z[((k + dk) & 255) as usize]];
                     let di = di as f32;
                     let dj = dj as f32;
                     let dk = dk as f32;
@@ -51,30 +51,30 @@ impl Perlin {
         accum
     }
 
-    pub fn perlin_generate() -> Vec<Vec3> {
+    pub fn perlin_generate(rng: &mut Rng) -> Vec<Vec3> {
         let mut p: Vec<Vec3> = Vec::with_capacity(256);
         for _i in 0..256 {
             p.push(Vec3::unit_vector(Vec3::new(
-                -1.0 + 2.0 * drand48(),
-                -1.0 + 2.0 * drand48(),
-                -1.0 + 2.0 * drand48(),
+                -1.0 + 2.0 * rng.drand48(),
+                -1.0 + 2.0 * rng.drand48(),
+                -1.0 + 2.0 * rng.drand48(),
             )));
         }
         p
     }
 
-    pub fn perlin_generate_perm() -> Vec<usize> {
+    pub fn perlin_generate_perm(rng: &mut Rng) -> Vec<usize> {
         let mut p: Vec<usize> = Vec::with_capacity(256);
         for i in 0..256 {
             p.push(i);
         }
-        Perlin::permute(&mut p, 256);
+        Perlin::permute(&mut p, 256, rng);
         p
     }
 
-    pub fn permute(p: &mut [usize], n: usize) {
+    pub fn permute(p: &mut [usize], n: usize, rng: &mut Rng) {
         for i in (0..n).rev() {
-            let target = (drand48() * (i + 1) as f32) as usize;
+            let target = rng.gen_range(i + 1);
             p.swap(i, target);
         }
     }
@@ -5,6 +5,9 @@ pub struct Ray {
     pub origin: Vec3,
     pub direction: Vec3,
     pub time: f32,
+    // Key of the camera sample and bounce the ray belongs to, for random decisions made while
+    // intersecting it, such as where it scatters in a participating medium.
+    pub seed: u64,
 }
 
 impl Ray {
@@ -13,9 +16,15 @@ impl Ray {
             origin,
             direction,
             time: ti,
+            seed: 0,
         }
     }
 
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
     pub fn origin(&self) -> Vec3 {
         self.origin
     }
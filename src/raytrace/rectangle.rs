@@ -1,11 +1,13 @@
 use super::aabb::AABB;
-use super::hitable::{HitRecord, Hitable};
+use super::hitable::{HitRecord, Hitable, TraversalStats};
+use super::light::{area_to_solid_angle, Light};
 use super::material::Material;
 use super::ray::Ray;
 use super::vec::Vec3;
 
 use std::sync::Arc;
 
+#[derive(Clone)]
 pub struct XY {
     x0: f32,
     x1: f32,
@@ -45,6 +47,7 @@ impl Hitable for XY {
             v: (y - self.y0) / (self.y1 - self.y0),
             p: r.point_at_parameter(t),
             normal: Vec3::new(0.0, 0.0, 1.0),
+            geometric_normal: Vec3::new(0.0, 0.0, 1.0),
             material: self.material.clone(),
         })
     }
@@ -54,8 +57,37 @@ impl Hitable for XY {
             max: Vec3::new(self.x1, self.y1, self.k + 0.0001),
         })
     }
+    fn collect_lights(&self, lights: &mut Vec<Arc<dyn Light>>) {
+        if self.material.is_emissive() {
+            lights.push(Arc::new(self.clone()));
+        }
+    }
+    fn collect_materials(&self, materials: &mut Vec<Arc<dyn Material>>) {
+        materials.push(Arc::clone(&self.material));
+    }
+}
+
+impl Light for XY {
+    fn pdf_value(&self, origin: Vec3, direction: Vec3) -> f32 {
+        match self.hit(Ray::new(origin, direction, 0.0), 0.001, f32::MAX) {
+            Some(rec) => {
+                let area = (self.x1 - self.x0) * (self.y1 - self.y0);
+                area_to_solid_angle(area, rec.t, direction, rec.normal)
+            }
+            None => 0.0,
+        }
+    }
+    fn random(&self, origin: Vec3, u: (f32, f32)) -> Vec3 {
+        let point = Vec3::new(
+            self.x0 + u.0 * (self.x1 - self.x0),
+            self.y0 + u.1 * (self.y1 - self.y0),
+            self.k,
+        );
+        point - origin
+    }
 }
 
+#[derive(Clone)]
 pub struct XZ {
     x0: f32,
     x1: f32,
@@ -95,6 +127,7 @@ impl Hitable for XZ {
             v: (z - self.z0) / (self.z1 - self.z0),
             p: r.point_at_parameter(t),
             normal: Vec3::new(0.0, 1.0, 0.0),
+            geometric_normal: Vec3::new(0.0, 1.0, 0.0),
             material: self.material.clone(),
         })
     }
@@ -104,8 +137,37 @@ impl Hitable for XZ {
             max: Vec3::new(self.x1, self.k + 0.0001, self.z1),
         })
     }
+    fn collect_lights(&self, lights: &mut Vec<Arc<dyn Light>>) {
+        if self.material.is_emissive() {
+            lights.push(Arc::new(self.clone()));
+        }
+    }
+    fn collect_materials(&self, materials: &mut Vec<Arc<dyn Material>>) {
+        materials.push(Arc::clone(&self.material));
+    }
 }
 
+impl Light for XZ {
+    fn pdf_value(&self, origin: Vec3, direction: Vec3) -> f32 {
+        match self.hit(Ray::new(origin, direction, 0.0), 0.001, f32::MAX) {
+            Some(rec) => {
+                let area = (self.x1 - self.x0) * (self.z1 - self.z0);
+                area_to_solid_angle(area, rec.t, direction, rec.normal)
+            }
+            None => 0.0,
+        }
+    }
+    fn random(&self, origin: Vec3, u: (f32, f32)) -> Vec3 {
+        let point = Vec3::new(
+            self.x0 + u.0 * (self.x1 - self.x0),
+            self.k,
+            self.z0 + u.1 * (self.z1 - self.z0),
+        );
+        point - origin
+    }
+}
+
+#[derive(Clone)]
 pub struct YZ {
     y0: f32,
     y1: f32,
@@ -145,6 +207,7 @@ impl Hitable for YZ {
             v: (z - self.z0) / (self.z1 - self.z0),
             p: r.point_at_parameter(t),
             normal: Vec3::new(1.0, 0.0, 0.0),
+            geometric_normal: Vec3::new(1.0, 0.0, 0.0),
             material: self.material.clone(),
         })
     }
@@ -154,6 +217,34 @@ impl Hitable for YZ {
             max: Vec3::new(self.k - 0.0001, self.y1, self.z1),
         })
     }
+    fn collect_lights(&self, lights: &mut Vec<Arc<dyn Light>>) {
+        if self.material.is_emissive() {
+            lights.push(Arc::new(self.clone()));
+        }
+    }
+    fn collect_materials(&self, materials: &mut Vec<Arc<dyn Material>>) {
+        materials.push(Arc::clone(&self.material));
+    }
+}
+
+impl Light for YZ {
+    fn pdf_value(&self, origin: Vec3, direction: Vec3) -> f32 {
+        match self.hit(Ray::new(origin, direction, 0.0), 0.001, f32::MAX) {
+            Some(rec) => {
+                let area = (self.y1 - self.y0) * (self.z1 - self.z0);
+                area_to_solid_angle(area, rec.t, direction, rec.normal)
+            }
+            None => 0.0,
+        }
+    }
+    fn random(&self, origin: Vec3, u: (f32, f32)) -> Vec3 {
+        let point = Vec3::new(
+            self.k,
+            self.y0 + u.0 * (self.y1 - self.y0),
+            self.z0 + u.1 * (self.z1 - self.z0),
+        );
+        point - origin
+    }
 }
 
 pub struct FlipNormal {
@@ -167,9 +258,19 @@ impl FlipNormal {
 }
 impl Hitable for FlipNormal {
     fn hit(&self, r: Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
-        match self.obj.hit(r, t_min, t_max) {
+        self.hit_counted(r, t_min, t_max, &mut TraversalStats::default())
+    }
+    fn hit_counted(
+        &self,
+        r: Ray,
+        t_min: f32,
+        t_max: f32,
+        stats: &mut TraversalStats,
+    ) -> Option<HitRecord> {
+        match self.obj.hit_counted(r, t_min, t_max, stats) {
             Some(rec) => Some(HitRecord {
                 normal: -rec.normal,
+                geometric_normal: -rec.geometric_normal,
                 ..rec
             }),
             None => None,
@@ -178,4 +279,10 @@ impl Hitable for FlipNormal {
     fn bounding_box(&self, t0: f32, t1: f32) -> Option<AABB> {
         self.obj.bounding_box(t0, t1)
     }
+    fn collect_lights(&self, lights: &mut Vec<Arc<dyn Light>>) {
+        self.obj.collect_lights(lights)
+    }
+    fn collect_materials(&self, materials: &mut Vec<Arc<dyn Material>>) {
+        self.obj.collect_materials(materials)
+    }
 }
@@ -0,0 +1,977 @@
+use super::adaptive::{AdaptiveSettings, PixelStats};
+use super::aov::{AovFilms, AovSample};
+use super::camera::Camera;
+use super::denoise::{denoise, DenoiseSettings};
+use super::environment::{EnvironmentLight, Environments, RayKind};
+use super::film::Film;
+use super::filter::{Filter, FilterKind};
+use super::hitable::{HitRecord, Hitable, TraversalStats};
+use super::hitable_list::HitableList;
+use super::integrator::{id_color, AoSettings, Integrator};
+use super::light::{Light, LightList};
+use super::material::Material;
+use super::output::heatmap;
+use super::ray::Ray;
+use super::rng::{mix, Rng};
+use super::sampler::{Sampler, SamplerKind};
+use super::tiles::{tiles, TileBounds, TileOrder};
+use super::tonemap::luminance;
+use super::vec::{onb, random_cosine_direction, Vec3};
+
+use rayon::prelude::*;
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+#[derive(Clone, Copy, Debug)]
+pub struct RenderSettings {
+    pub width: usize,
+    pub height: usize,
+    pub samples: usize,
+    pub max_depth: usize,
+    // Paths shorter than this are never terminated by Russian roulette.
+    pub rr_min_depth: usize,
+    // Same seed and settings give the same image, whatever the thread count.
+    pub seed: u64,
+    pub sampler: SamplerKind,
+    pub filter: FilterKind,
+    // Filter radius in pixels, None for the filter's default.
+    pub filter_radius: Option<f32>,
+    // Also record the first-hit AOV buffers.
+    pub aovs: bool,
+    // Denoise the beauty pass, guided by the AOVs, which are then recorded as well.
+    pub denoise: Option<DenoiseSettings>,
+    // With adaptive sampling `samples` is the minimum per pixel.
+    pub adaptive: Option<AdaptiveSettings>,
+    // Largest channel value a sample may get from indirect light, i.e. anything but emission
+    // seen directly or after one bounce and light sampled at the first hit.
+    pub indirect_clamp: Option<f32>,
+    // Number of buckets the beauty film takes the median of their means over, None for a
+    // plain mean.
+    pub median_of_means: Option<usize>,
+    pub integrator: Integrator,
+    pub ao: AoSettings,
+    // Edge length of the square tiles the image is split into, in pixels.
+    pub tile_size: usize,
+    pub tile_order: TileOrder,
+}
+
+impl Default for RenderSettings {
+    fn default() -> Self {
+        RenderSettings {
+            width: 500,
+            height: 500,
+            samples: 10,
+            max_depth: 50,
+            rr_min_depth: 3,
+            seed: 0,
+            sampler: SamplerKind::Sobol,
+            filter: FilterKind::Box,
+            filter_radius: None,
+            aovs: false,
+            denoise: None,
+            adaptive: None,
+            indirect_clamp: None,
+            median_of_means: None,
+            integrator: Integrator::Path,
+            ao: AoSettings::default(),
+            tile_size: 32,
+            tile_order: TileOrder::Spiral,
+        }
+    }
+}
+
+pub struct RenderLayers {
+    pub beauty: Film,
+    // Present when `RenderSettings::aovs` or `RenderSettings::denoise` is set.
+    pub aovs: Option<AovFilms>,
+    pub denoised: Option<Vec<Vec<Vec3>>>,
+    // Depth and heatmap integrators, scaled or false-colored for display.
+    pub visualized: Option<Vec<Vec<Vec3>>>,
+    // Samples taken per pixel, rows top first.
+    pub sample_counts: Vec<Vec<usize>>,
+}
+
+impl RenderLayers {
+    // The visualized or denoised beauty pass if there is one, rows top first.
+    pub fn image(&self) -> Vec<Vec<Vec3>> {
+        match (&self.visualized, &self.denoised) {
+            (Some(rows), _) | (None, Some(rows)) => rows.clone(),
+            (None, None) => self.beauty.to_rows(),
+        }
+    }
+}
+
+// Accumulated samples of a render in progress. Passes add to it and checkpoints store it.
+pub struct RenderState {
+    pub beauty: Film,
+    pub aovs: Option<AovFilms>,
+    // Sample statistics per pixel, rows top first. The count is also where each pixel's
+    // sample sequence continues.
+    pub stats: Vec<PixelStats>,
+    // Samples per pixel the finished passes have asked for.
+    pub target: usize,
+}
+
+impl RenderState {
+    // Samples per pixel taken so far, averaged over the image.
+    pub fn mean_samples(&self) -> f32 {
+        let total: usize = self.stats.iter().map(|s| s.count).sum();
+        total as f32 / self.stats.len().max(1) as f32
+    }
+
+    // Estimated noise of the image: the relative standard error of the pixel luminances,
+    // averaged over the pixels. Infinite until every pixel has two samples.
+    pub fn error(&self) -> f32 {
+        let total: f32 = self.stats.iter().map(|s| s.relative_error()).sum();
+        total / self.stats.len().max(1) as f32
+    }
+}
+
+// Work done on one tile in a pass, reported as soon as the tile is finished.
+#[derive(Clone, Copy, Debug)]
+pub struct TileProgress {
+    pub pixels: usize,
+    // Camera, bounce and shadow rays traced.
+    pub rays: u64,
+}
+
+// Mixed into the render seed for the seeds rays carry, which would otherwise repeat the first
+// draw of the independent sampler, as both come from `Rng::for_sample`.
+const RAY_SEED_STREAM: u64 = 0x7261_795f_7365_6564;
+
+// What a tile rendered, kept apart until the tiles are merged.
+struct RenderedTile {
+    bounds: TileBounds,
+    beauty: Film,
+    aovs: Option<AovFilms>,
+    // Updated statistics of the tile's pixels, row by row.
+    stats: Vec<PixelStats>,
+    rays: u64,
+}
+
+pub struct Renderer {
+    pub world: HitableList,
+    pub camera: Camera,
+    pub environments: Environments,
+    pub settings: RenderSettings,
+    pub lights: LightList,
+    pub filter: Box<dyn Filter>,
+    // IDs of the world's materials by address, numbered from one in traversal order.
+    materials: HashMap<usize, u32>,
+}
+
+impl Renderer {
+    pub fn new(
+        world: HitableList,
+        camera: Camera,
+        environments: Environments,
+        settings: RenderSettings,
+    ) -> Self {
+        let mut lights = LightList::from_world(&world);
+        if environments.diffuse.is_samplable() {
+            lights
+                .lights
+                .push(Arc::new(EnvironmentLight::new(Arc::clone(
+                    &environments.diffuse,
+                ))));
+        }
+        let radius = settings
+            .filter_radius
+            .unwrap_or_else(|| settings.filter.default_radius());
+        let filter = settings.filter.create(radius);
+        let mut all_materials = vec![];
+        world.collect_materials(&mut all_materials);
+        let mut materials = HashMap::new();
+        for material in &all_materials {
+            let next = materials.len() as u32 + 1;
+            materials.entry(material_key(material)).or_insert(next);
+        }
+        Renderer {
+            world,
+            camera,
+            environments,
+            settings,
+            lights,
+            filter,
+            materials,
+        }
+    }
+
+    pub fn render(&self) -> Vec<Vec<Vec3>> {
+        self.render_with_progress(|_| {})
+    }
+
+    // Returns linear radiance rows, top row first. `tile_done` is called once per finished tile.
+    pub fn render_with_progress<F: Fn(&TileProgress) + Sync>(
+        &self,
+        tile_done: F,
+    ) -> Vec<Vec<Vec3>> {
+        self.render_layers(tile_done).image()
+    }
+
+    // Renders in a single pass.
+    pub fn render_layers<F: Fn(&TileProgress) + Sync>(&self, tile_done: F) -> RenderLayers {
+        let mut state = self.new_state();
+        self.render_pass(&mut state, self.target_samples(), tile_done);
+        self.finish(state)
+    }
+
+    // Samples per pixel a complete render asks for; adaptive sampling may stop short of it.
+    pub fn target_samples(&self) -> usize {
+        match &self.settings.adaptive {
+            Some(adaptive) => adaptive.max_samples.max(self.settings.samples),
+            None => self.settings.samples,
+        }
+    }
+
+    pub fn records_aovs(&self) -> bool {
+        self.settings.aovs || self.settings.denoise.is_some()
+    }
+
+    fn buckets(&self) -> usize {
+        self.settings.median_of_means.unwrap_or(1)
+    }
+
+    pub fn new_state(&self) -> RenderState {
+        let nx = self.settings.width;
+        let ny = self.settings.height;
+        RenderState {
+            beauty: Film::new(nx, ny).with_buckets(self.buckets()),
+            aovs: if self.records_aovs() {
+                Some(AovFilms::new(nx, ny))
+            } else {
+                None
+            },
+            stats: vec![PixelStats::default(); nx * ny],
+            target: 0,
+        }
+    }
+
+    // Brings every pixel of `state` up to `target` samples. With adaptive sampling, pixels past
+    // the minimum only get samples while their error is above the threshold.
+    // Tiles are handed out in `settings.tile_order` to one worker per rayon thread. Each tile
+    // splats into its own film, padded by the filter radius, and the tile films are merged in
+    // tile order so the result does not depend on scheduling.
+    pub fn render_pass<F: Fn(&TileProgress) + Sync>(
+        &self,
+        state: &mut RenderState,
+        target: usize,
+        tile_done: F,
+    ) {
+        let tiles = tiles(
+            self.settings.width,
+            self.settings.height,
+            self.settings.tile_size,
+            self.settings.tile_order,
+        );
+        let next = AtomicUsize::new(0);
+        let stats = &state.stats;
+        let workers: Vec<Vec<(usize, RenderedTile)>> = (0..rayon::current_num_threads())
+            .into_par_iter()
+            .map(|_| {
+                let mut done = Vec::new();
+                loop {
+                    let index = next.fetch_add(1, Ordering::Relaxed);
+                    if index >= tiles.len() {
+                        break;
+                    }
+                    let tile = self.render_tile(tiles[index], target, stats);
+                    tile_done(&TileProgress {
+                        pixels: tiles[index].pixels(),
+                        rays: tile.rays,
+                    });
+                    done.push((index, tile));
+                }
+                done
+            })
+            .collect();
+        let mut rendered: Vec<(usize, RenderedTile)> = workers.into_iter().flatten().collect();
+        rendered.sort_by_key(|(index, _)| *index);
+
+        let nx = self.settings.width;
+        for (_, tile) in &rendered {
+            state.beauty.merge(&tile.beauty);
+            if let (Some(aovs), Some(tile_aovs)) = (&mut state.aovs, &tile.aovs) {
+                aovs.merge(tile_aovs);
+            }
+            let bounds = tile.bounds;
+            for (y, row) in tile.stats.chunks(bounds.width()).enumerate() {
+                let start = (bounds.y0 + y) * nx + bounds.x0;
+                state.stats[start..start + row.len()].copy_from_slice(row);
+            }
+        }
+        state.target = state.target.max(target);
+    }
+
+    fn render_tile(&self, bounds: TileBounds, target: usize, stats: &[PixelStats]) -> RenderedTile {
+        let nx = self.settings.width;
+        let ny = self.settings.height;
+        let TileBounds { x0, y0, x1, y1 } = bounds;
+        let mut tile = RenderedTile {
+            bounds,
+            beauty: Film::for_tile(nx, ny, x0, y0, x1, y1, self.filter.radius())
+                .with_buckets(self.buckets()),
+            aovs: if self.records_aovs() {
+                Some(AovFilms::with_bounds(nx, ny, x0, y0, x1, y1))
+            } else {
+                None
+            },
+            stats: Vec::with_capacity(bounds.pixels()),
+            rays: 0,
+        };
+        for y in y0..y1 {
+            for x in x0..x1 {
+                let mut pixel_stats = stats[y * nx + x];
+                self.render_pixel(x, y, target, &mut pixel_stats, &mut tile);
+                tile.stats.push(pixel_stats);
+            }
+        }
+        tile
+    }
+
+    // Final layers of a render, denoised if the settings ask for it.
+    pub fn finish(&self, state: RenderState) -> RenderLayers {
+        let nx = self.settings.width;
+        let denoised = match (&self.settings.denoise, &state.aovs) {
+            (Some(settings), Some(aovs)) if self.settings.integrator == Integrator::Path => {
+                Some(denoise(&state.beauty, aovs, settings))
+            }
+            _ => None,
+        };
+        let visualized = match self.settings.integrator {
+            Integrator::Depth => {
+                let rows = state.beauty.to_rows();
+                let max = rows
+                    .iter()
+                    .flatten()
+                    .fold(0.0f32, |m, c| m.max(c.r()))
+                    .max(f32::MIN_POSITIVE);
+                Some(
+                    rows.iter()
+                        .map(|row| row.iter().map(|c| *c / max).collect())
+                        .collect(),
+                )
+            }
+            Integrator::AabbTests | Integrator::PrimitiveTests => {
+                let counts: Vec<Vec<f32>> = state
+                    .beauty
+                    .to_rows()
+                    .iter()
+                    .map(|row| row.iter().map(|c| c.r()).collect())
+                    .collect();
+                Some(heatmap(&counts))
+            }
+            _ => None,
+        };
+        RenderLayers {
+            sample_counts: state
+                .stats
+                .chunks(nx)
+                .map(|row| row.iter().map(|s| s.count).collect())
+                .collect(),
+            beauty: state.beauty,
+            aovs: state.aovs,
+            denoised,
+            visualized,
+        }
+    }
+
+    // Continues raster pixel (x, y) from the samples `stats` has seen, towards `target`.
+    fn render_pixel(
+        &self,
+        x: usize,
+        y: usize,
+        target: usize,
+        stats: &mut PixelStats,
+        tile: &mut RenderedTile,
+    ) {
+        let mut sampler = self
+            .settings
+            .sampler
+            .create(self.settings.samples, self.settings.seed);
+        let (min, max) = match &self.settings.adaptive {
+            Some(adaptive) => (
+                self.settings.samples.min(target),
+                target.min(adaptive.max_samples),
+            ),
+            None => (target, target),
+        };
+        let mut end = min.max(stats.count);
+        loop {
+            for s in stats.count..end {
+                let col = self.render_sample(x, y, s, sampler.as_mut(), tile);
+                stats.add(luminance(col));
+            }
+            match &self.settings.adaptive {
+                Some(adaptive)
+                    if stats.count < max && stats.relative_error() > adaptive.threshold =>
+                {
+                    end = (stats.count + adaptive.batch_size.max(1)).min(max);
+                }
+                _ => return,
+            }
+        }
+    }
+
+    // Traces sample `s` of raster pixel (x, y) into `tile` and returns its radiance.
+    fn render_sample(
+        &self,
+        x: usize,
+        y: usize,
+        s: usize,
+        sampler: &mut dyn Sampler,
+        tile: &mut RenderedTile,
+    ) -> Vec3 {
+        let nx = self.settings.width;
+        let ny = self.settings.height;
+        // Samplers number rows from the bottom, as the camera measures v upwards.
+        sampler.start_pixel_sample(x, ny - 1 - y, s);
+        let (fx, fy) = film_position(x, y, sampler.get_2d());
+        let u = fx / nx as f32;
+        let v = (ny as f32 - fy) / ny as f32;
+        let lens = sampler.get_2d();
+        let time = sampler.get_1d();
+        let seed = Rng::for_sample(mix(self.settings.seed ^ RAY_SEED_STREAM), x, y, s).next_u32();
+        let r = self.camera.get_ray(u, v, lens, time).with_seed(seed as u64);
+        let mut aov = AovSample::default();
+        let col = match self.settings.integrator {
+            Integrator::Path => self.color(r, sampler, &mut aov, &mut tile.rays),
+            Integrator::AmbientOcclusion => {
+                self.ambient_occlusion(r, sampler, &mut aov, &mut tile.rays)
+            }
+            _ => self.debug_color(r, &mut aov, &mut tile.rays),
+        };
+        if let Some(aovs) = &mut tile.aovs {
+            aovs.add_sample(x, y, s, &aov);
+        }
+        tile.beauty
+            .add_bucket_sample(s, fx, fy, col, self.filter.as_ref());
+        col
+    }
+
+    // Radiance along `r`; the first hit is also recorded in `aov` and every ray traced is
+    // counted in `rays`.
+    fn color(
+        &self,
+        r: Ray,
+        sampler: &mut dyn Sampler,
+        aov: &mut AovSample,
+        rays: &mut u64,
+    ) -> Vec3 {
+        // Light reaching the first hit directly, and everything else, which may be clamped.
+        let mut radiance = Vec3::new(0.0, 0.0, 0.0);
+        let mut indirect = Vec3::new(0.0, 0.0, 0.0);
+        let mut throughput = Vec3::new(1.0, 1.0, 1.0);
+        let mut ray = r;
+        // Density the previous vertex sampled `ray` with, None after the camera or a specular
+        // bounce, where emission cannot be reached by light sampling.
+        let mut bsdf_pdf: Option<f32> = None;
+        // What `ray` left, which picks the environment it sees if it escapes.
+        let mut kind = RayKind::Camera;
+        let mut depth = 0;
+        loop {
+            *rays += 1;
+            let (object, rec) = match self.world.hit_with_index(ray, 0.001, f32::MAX) {
+                Some(hit) => hit,
+                None => {
+                    let mut background =
+                        throughput * self.environments.get(kind).radiance(ray.direction());
+                    if let Some(bsdf_pdf) = bsdf_pdf {
+                        let light_pdf = self.lights.pdf_value(ray.origin(), ray.direction());
+                        background *= power_heuristic(bsdf_pdf, light_pdf);
+                    }
+                    if depth < 2 {
+                        radiance += background;
+                    } else {
+                        indirect += background;
+                    }
+                    break;
+                }
+            };
+            if depth == 0 {
+                *aov = self.first_hit_aov(object, &rec);
+            }
+            // Emission still counts at the last vertex, as the light sample taken at the
+            // previous one was weighted on the assumption that it would.
+            if rec.material.is_emissive() {
+                let mut emitted = rec.material.emitted(rec.u, rec.v, rec.p);
+                if let Some(bsdf_pdf) = bsdf_pdf {
+                    let light_pdf = self.lights.pdf_value(ray.origin(), ray.direction());
+                    emitted *= power_heuristic(bsdf_pdf, light_pdf);
+                }
+                if depth < 2 {
+                    radiance += throughput * emitted;
+                } else {
+                    indirect += throughput * emitted;
+                }
+            }
+            if depth >= self.settings.max_depth {
+                break;
+            }
+            // Every bounce draws the same dimensions, used or not, to keep them aligned.
+            let bsdf_uc = sampler.get_1d();
+            let bsdf_u = sampler.get_2d();
+            let light_u = sampler.get_2d();
+            let rr_u = sampler.get_1d();
+            let srec = match rec.material.sample(&ray, &rec, bsdf_uc, bsdf_u) {
+                Some(srec) => srec,
+                None => break,
+            };
+            if srec.is_specular {
+                bsdf_pdf = None;
+                kind = RayKind::Specular;
+            } else {
+                let direct = throughput * self.sample_lights(&ray, &rec, light_u, rays);
+                if depth == 0 {
+                    radiance += direct;
+                } else {
+                    indirect += direct;
+                }
+                bsdf_pdf = Some(srec.pdf);
+                kind = RayKind::Diffuse;
+            }
+            throughput *= srec.attenuation;
+            ray = srec.ray.with_seed(mix(ray.seed));
+            depth += 1;
+            if depth >= self.settings.rr_min_depth {
+                let q = (1.0 - throughput.max_component()).max(0.05);
+                if rr_u < q {
+                    break;
+                }
+                throughput /= 1.0 - q;
+            }
+        }
+        if let Some(limit) = self.settings.indirect_clamp {
+            // Scaling the whole color keeps the hue of clamped samples.
+            let max = indirect.max_component();
+            if max > limit {
+                indirect *= limit / max;
+            }
+        }
+        radiance + indirect
+    }
+
+    // Unoccluded fraction of the hemisphere at the first hit along `r`, estimated with
+    // cosine-weighted rays around the normal facing the camera. Zero where `r` escapes.
+    fn ambient_occlusion(
+        &self,
+        r: Ray,
+        sampler: &mut dyn Sampler,
+        aov: &mut AovSample,
+        rays: &mut u64,
+    ) -> Vec3 {
+        *rays += 1;
+        let (object, rec) = match self.world.hit_with_index(r, 0.001, f32::MAX) {
+            Some(hit) => hit,
+            None => return Vec3::new(0.0, 0.0, 0.0),
+        };
+        *aov = self.first_hit_aov(object, &rec);
+        let normal = if Vec3::dot(&rec.normal, &r.direction()) > 0.0 {
+            -rec.normal
+        } else {
+            rec.normal
+        };
+        let (u, v, w) = onb(normal);
+        let samples = self.settings.ao.samples.max(1);
+        let mut unoccluded = 0;
+        for _ in 0..samples {
+            let d = random_cosine_direction(sampler.get_2d());
+            let direction = d.x() * u + d.y() * v + d.z() * w;
+            let ao_ray = Ray::new(rec.p, direction, r.time()).with_seed(r.seed);
+            *rays += 1;
+            if self
+                .world
+                .hit(ao_ray, 0.001, self.settings.ao.max_distance)
+                .is_none()
+            {
+                unoccluded += 1;
+            }
+        }
+        let fraction = unoccluded as f32 / samples as f32;
+        Vec3::new(fraction, fraction, fraction)
+    }
+
+    // What a debug integrator shows along camera ray `r`.
+    fn debug_color(&self, r: Ray, aov: &mut AovSample, rays: &mut u64) -> Vec3 {
+        *rays += 1;
+        let mut stats = TraversalStats::default();
+        let hit = self
+            .world
+            .hit_with_index_counted(r, 0.001, f32::MAX, &mut stats);
+        let gray = |v: f32| Vec3::new(v, v, v);
+        let rec = match (self.settings.integrator, hit) {
+            (Integrator::AabbTests, _) => return gray(stats.aabb_tests as f32),
+            (Integrator::PrimitiveTests, _) => return gray(stats.primitive_tests as f32),
+            (_, None) => return Vec3::new(0.0, 0.0, 0.0),
+            (_, Some((object, rec))) => {
+                *aov = self.first_hit_aov(object, &rec);
+                rec
+            }
+        };
+        let one = Vec3::new(1.0, 1.0, 1.0);
+        match self.settings.integrator {
+            Integrator::ShadingNormal => 0.5 * (rec.normal.normalize() + one),
+            Integrator::GeometricNormal => 0.5 * (rec.geometric_normal.normalize() + one),
+            Integrator::Depth => gray(self.camera.depth(rec.p)),
+            Integrator::Uv => Vec3::new(rec.u, rec.v, 0.0),
+            Integrator::MaterialId => id_color(self.material_id(&rec.material)),
+            _ => Vec3::new(0.0, 0.0, 0.0),
+        }
+    }
+
+    fn first_hit_aov(&self, object: usize, rec: &HitRecord) -> AovSample {
+        AovSample {
+            albedo: rec.material.albedo(rec),
+            normal: rec.normal.normalize(),
+            position: rec.p,
+            depth: self.camera.depth(rec.p),
+            uv: (rec.u, rec.v),
+            object_id: object as u32 + 1,
+        }
+    }
+
+    // Zero for materials the world did not report.
+    fn material_id(&self, material: &Arc<dyn Material>) -> u32 {
+        self.materials
+            .get(&material_key(material))
+            .copied()
+            .unwrap_or(0)
+    }
+
+    // Light-sampled half of the multiple importance sampling estimate at `rec`. Whatever the
+    // shadow ray sees counts, which is the diffuse environment when it escapes.
+    fn sample_lights(&self, r_in: &Ray, rec: &HitRecord, u: (f32, f32), rays: &mut u64) -> Vec3 {
+        if self.lights.is_empty() {
+            return Vec3::new(0.0, 0.0, 0.0);
+        }
+        let direction = self.lights.random(rec.p, u);
+        let light_pdf = self.lights.pdf_value(rec.p, direction);
+        let f = rec.material.eval(r_in, rec, direction);
+        if light_pdf <= 0.0 || (f.r() <= 0.0 && f.g() <= 0.0 && f.b() <= 0.0) {
+            return Vec3::new(0.0, 0.0, 0.0);
+        }
+        let shadow_ray = Ray::new(rec.p, direction, r_in.time()).with_seed(r_in.seed);
+        *rays += 1;
+        let emitted = match self.world.hit(shadow_ray, 0.001, f32::MAX) {
+            Some(light_rec) if light_rec.material.is_emissive() => {
+                light_rec
+                    .material
+                    .emitted(light_rec.u, light_rec.v, light_rec.p)
+            }
+            Some(_) => return Vec3::new(0.0, 0.0, 0.0),
+            None => self.environments.diffuse.radiance(direction),
+        };
+        let bsdf_pdf = rec.material.pdf(r_in, rec, direction);
+        f * emitted * (power_heuristic(light_pdf, bsdf_pdf) / light_pdf)
+    }
+}
+
+// Raster position of a sample at offset `d` in [0, 1)^2 within pixel (x, y). It lies in
+// [x, x + 1) x [y, y + 1), the half-open pixel the box filter gives it to.
+fn film_position(x: usize, y: usize, d: (f32, f32)) -> (f32, f32) {
+    (x as f32 + d.0, y as f32 + d.1)
+}
+
+fn material_key(material: &Arc<dyn Material>) -> usize {
+    Arc::as_ptr(material) as *const () as usize
+}
+
+fn power_heuristic(pdf: f32, other_pdf: f32) -> f32 {
+    let a = pdf * pdf;
+    let b = other_pdf * other_pdf;
+    if a + b <= 0.0 {
+        return 0.0;
+    }
+    a / (a + b)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::aov::Aov;
+    use super::super::environment::ConstantEnvironment;
+    use super::super::material::{Lambertian, Metal};
+    use super::super::rectangle::XZ;
+    use super::super::sphere::Sphere;
+    use super::super::texture::ConstantTexture;
+    use super::*;
+    use crate::scenes;
+
+    // The smoke-filled Cornell box, lit by a constant sky through its open front.
+    fn renderer(seed: u64, sampler: SamplerKind) -> Renderer {
+        let scene = scenes::load("cornell_smoke", seed).unwrap();
+        let camera = Camera::new(
+            scene.look_from,
+            scene.look_at,
+            Vec3::new(0.0, 1.0, 0.0),
+            scene.vfov,
+            1.0,
+            scene.aperture,
+            scene.focus_dist,
+            0.0,
+            1.0,
+        );
+        let sky = Arc::new(ConstantEnvironment::new(Vec3::new(1.0, 1.0, 1.0)));
+        let settings = RenderSettings {
+            width: 20,
+            height: 20,
+            samples: 4,
+            max_depth: 8,
+            seed,
+            sampler,
+            tile_size: 6,
+            ..RenderSettings::default()
+        };
+        Renderer::new(scene.world, camera, Environments::new(sky), settings)
+    }
+
+    // A unit sphere of `material` filling the frame.
+    fn sphere(
+        material: Arc<dyn Material>,
+        environments: Environments,
+        settings: RenderSettings,
+    ) -> Renderer {
+        let mut world = HitableList::new(1);
+        world.add(Box::new(Sphere::new(
+            Vec3::new(0.0, 0.0, 0.0),
+            1.0,
+            material,
+        )));
+        let camera = Camera::new(
+            Vec3::new(0.0, 0.0, 4.0),
+            Vec3::new(0.0, 0.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            15.0,
+            1.0,
+            0.0,
+            4.0,
+            0.0,
+            1.0,
+        );
+        Renderer::new(world, camera, environments, settings)
+    }
+
+    // A gray sphere under a white sky. Every bounce off it escapes, so each pixel converges
+    // to the albedo.
+    fn furnace(settings: RenderSettings) -> Renderer {
+        let gray = ConstantTexture::new(Vec3::new(0.5, 0.5, 0.5));
+        let sky = Arc::new(ConstantEnvironment::new(Vec3::new(1.0, 1.0, 1.0)));
+        sphere(
+            Arc::new(Lambertian::new(Box::new(gray))),
+            Environments::new(sky),
+            settings,
+        )
+    }
+
+    // The furnace camera with a view wide enough to show the sky around the sphere.
+    fn wide_camera() -> Camera {
+        Camera::new(
+            Vec3::new(0.0, 0.0, 4.0),
+            Vec3::new(0.0, 0.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            60.0,
+            1.0,
+            0.0,
+            4.0,
+            0.0,
+            1.0,
+        )
+    }
+
+    fn mean(rows: &[Vec<Vec3>]) -> f32 {
+        let pixels = rows.iter().flatten().map(|c| c.g()).collect::<Vec<f32>>();
+        pixels.iter().sum::<f32>() / pixels.len() as f32
+    }
+
+    fn render(renderer: &Renderer, threads: usize) -> Vec<u32> {
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build()
+            .unwrap();
+        let rows = pool.install(|| renderer.render());
+        rows.iter()
+            .flatten()
+            .flat_map(|c| vec![c.r(), c.g(), c.b()])
+            .map(f32::to_bits)
+            .collect()
+    }
+
+    #[test]
+    fn samples_land_in_their_own_pixel() {
+        let filter = FilterKind::Box.create(FilterKind::Box.default_radius());
+        for &d in &[(0.0, 0.0), (0.0, 0.999), (0.999, 0.0), (0.5, 0.5)] {
+            // A one-pixel tile keeps nothing that falls outside it.
+            let mut film = Film::with_bounds(4, 4, 2, 1, 3, 2);
+            let (fx, fy) = film_position(2, 1, d);
+            film.add_sample(fx, fy, Vec3::new(1.0, 1.0, 1.0), filter.as_ref());
+            assert_eq!(film.pixel(2, 1).weight, 1.0, "{:?}", d);
+        }
+    }
+
+    #[test]
+    fn same_seed_renders_identically_on_any_thread_count() {
+        for name in &["independent", "sobol"] {
+            let sampler = SamplerKind::from_name(name).unwrap();
+            let image = render(&renderer(5, sampler), 1);
+            assert!(image.iter().any(|bits| *bits != 0), "{}", name);
+            assert_eq!(image, render(&renderer(5, sampler), 3), "{}", name);
+            assert_ne!(image, render(&renderer(6, sampler), 3), "{}", name);
+        }
+    }
+
+    #[test]
+    fn mis_weights_sum_to_one() {
+        for &(a, b) in &[
+            (1.0, 1.0),
+            (0.3, 2.0),
+            (5.0, 1e-3),
+            (0.0, 0.7),
+            (1e-20, 1e-20),
+        ] {
+            let sum = power_heuristic(a, b) + power_heuristic(b, a);
+            assert!((sum - 1.0).abs() < 1e-6, "{} {} {}", a, b, sum);
+        }
+        assert_eq!(power_heuristic(0.0, 0.0), 0.0);
+    }
+
+    // Roulette from the first bounce on must add noise but no bias.
+    #[test]
+    fn russian_roulette_keeps_the_mean() {
+        for &rr_min_depth in &[0, 1, 50] {
+            let settings = RenderSettings {
+                width: 16,
+                height: 16,
+                samples: 64,
+                max_depth: 50,
+                rr_min_depth,
+                ..RenderSettings::default()
+            };
+            let mean = mean(&furnace(settings).render());
+            assert!((mean - 0.5).abs() < 0.015, "{} {}", rr_min_depth, mean);
+        }
+    }
+
+    // The furnace sphere seen head on: its nearest point is 3 units in front of the camera.
+    #[test]
+    fn aovs_describe_the_first_hit() {
+        let settings = RenderSettings {
+            width: 9,
+            height: 9,
+            samples: 4,
+            aovs: true,
+            ..RenderSettings::default()
+        };
+        let layers = furnace(settings).render_layers(|_| {});
+        let aovs = layers.aovs.unwrap();
+        let centre = |aov: Aov| aovs.film(aov).value(4, 4);
+        assert!((centre(Aov::Depth).r() - 3.0).abs() < 0.01);
+        assert!((centre(Aov::Position) - Vec3::new(0.0, 0.0, 1.0)).length() < 0.02);
+        assert!((centre(Aov::Normal) - Vec3::new(0.0, 0.0, 1.0)).length() < 0.02);
+        assert_eq!(centre(Aov::Albedo).g(), 0.5);
+        assert_eq!(centre(Aov::ObjectId).r(), 1.0);
+    }
+
+    // With a wider view the corners show only the sky, which has no noise and so needs no
+    // more than the first samples. Russian roulette from the first bounce makes the sphere
+    // noisy, so it runs to the limit.
+    #[test]
+    fn adaptive_sampling_stops_where_pixels_converge() {
+        let settings = |threshold: f32| RenderSettings {
+            width: 8,
+            height: 8,
+            samples: 16,
+            rr_min_depth: 0,
+            adaptive: Some(AdaptiveSettings {
+                max_samples: 64,
+                batch_size: 8,
+                threshold,
+            }),
+            ..RenderSettings::default()
+        };
+        let counts = |threshold: f32| {
+            let mut renderer = furnace(settings(threshold));
+            renderer.camera = wide_camera();
+            renderer.render_layers(|_| {}).sample_counts
+        };
+        let strict = counts(1e-6);
+        assert_eq!((strict[0][0], strict[7][7]), (16, 16));
+        assert_eq!(strict[4][4], 64);
+        let loose = counts(10.0);
+        assert!(loose.iter().flatten().all(|&count| count == 16));
+    }
+
+    // The furnace sphere alone is unoccluded. On a wide floor the lower half of the
+    // hemisphere facing the camera is blocked, unless the floor is beyond `max_distance`.
+    #[test]
+    fn ambient_occlusion_sees_the_floor() {
+        let ao = |floor: bool, max_distance: f32| {
+            let settings = RenderSettings {
+                width: 9,
+                height: 9,
+                samples: 4,
+                integrator: Integrator::AmbientOcclusion,
+                ao: AoSettings {
+                    samples: 256,
+                    max_distance,
+                },
+                ..RenderSettings::default()
+            };
+            let mut renderer = furnace(settings);
+            renderer.camera = wide_camera();
+            if floor {
+                let gray = ConstantTexture::new(Vec3::new(0.5, 0.5, 0.5));
+                let material = Arc::new(Lambertian::new(Box::new(gray)));
+                renderer.world.add(Box::new(XZ::new(
+                    -100.0, 100.0, -100.0, 100.0, -1.0, material,
+                )));
+            }
+            renderer.render()
+        };
+        let open = ao(false, f32::MAX);
+        assert_eq!(open[4][4].g(), 1.0);
+        assert_eq!(open[0][0].g(), 0.0);
+        let floor = ao(true, f32::MAX);
+        assert!((floor[4][4].g() - 0.5).abs() < 0.05, "{:?}", floor[4][4]);
+        assert_eq!(ao(true, 0.5)[4][4].g(), 1.0);
+    }
+
+    // The backdrop, a mirror and a diffuse surface each see their own environment.
+    #[test]
+    fn rays_see_the_environment_of_their_kind() {
+        let environment = |r, g, b| Arc::new(ConstantEnvironment::new(Vec3::new(r, g, b)));
+        let environments = Environments::new(environment(0.0, 0.0, 1.0))
+            .with_camera(environment(1.0, 0.0, 0.0))
+            .with_specular(environment(0.0, 1.0, 0.0));
+        let render = |material: Arc<dyn Material>| {
+            let settings = RenderSettings {
+                width: 9,
+                height: 9,
+                samples: 4,
+                ..RenderSettings::default()
+            };
+            let mut renderer = sphere(material, environments.clone(), settings);
+            renderer.camera = wide_camera();
+            renderer.render()
+        };
+        let close = |a: Vec3, b: Vec3| (a - b).length() < 1e-4;
+        let gray = ConstantTexture::new(Vec3::new(0.5, 0.5, 0.5));
+        let diffuse = render(Arc::new(Lambertian::new(Box::new(gray))));
+        assert!(
+            close(diffuse[0][0], Vec3::new(1.0, 0.0, 0.0)),
+            "{:?}",
+            diffuse[0][0]
+        );
+        assert!(
+            close(diffuse[4][4], Vec3::new(0.0, 0.0, 0.5)),
+            "{:?}",
+            diffuse[4][4]
+        );
+        let mirror = render(Arc::new(Metal::new(Vec3::new(0.8, 0.8, 0.8), 0.0)));
+        assert!(
+            close(mirror[4][4], Vec3::new(0.0, 0.8, 0.0)),
+            "{:?}",
+            mirror[4][4]
+        );
+    }
+}
@@ -0,0 +1,68 @@
+use super::ray::Ray;
+
+// PCG32 (XSH-RR). Small, fast and gives the same sequence on every platform.
+#[derive(Clone, Debug)]
+pub struct Rng {
+    state: u64,
+    inc: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Rng::with_stream(seed, 0)
+    }
+
+    pub fn with_stream(seed: u64, stream: u64) -> Self {
+        let mut rng = Rng {
+            state: 0,
+            inc: (stream << 1) | 1,
+        };
+        rng.next_u32();
+        rng.state = rng.state.wrapping_add(seed);
+        rng.next_u32();
+        rng
+    }
+
+    // Independent generator for one camera sample, regardless of which thread traces it.
+    pub fn for_sample(seed: u64, x: usize, y: usize, sample: usize) -> Self {
+        let pixel = mix(((y as u64) << 32) | x as u64);
+        Rng::with_stream(mix(seed ^ mix(sample as u64)), pixel)
+    }
+
+    // Generator keyed on a ray and its seed, for decisions made inside `Hitable::hit`.
+    pub fn from_ray(r: &Ray) -> Self {
+        let mut h = mix(r.seed ^ mix(r.time().to_bits() as u64));
+        for i in 0..3 {
+            h = mix(h ^ r.origin()[i].to_bits() as u64);
+            h = mix(h ^ r.direction()[i].to_bits() as u64);
+        }
+        Rng::new(h)
+    }
+
+    pub fn next_u32(&mut self) -> u32 {
+        let old = self.state;
+        self.state = old
+            .wrapping_mul(6_364_136_223_846_793_005)
+            .wrapping_add(self.inc);
+        let xorshifted = (((old >> 18) ^ old) >> 27) as u32;
+        let rot = (old >> 59) as u32;
+        xorshifted.rotate_right(rot)
+    }
+
+    // Uniform in [0, 1).
+    pub fn drand48(&mut self) -> f32 {
+        (self.next_u32() >> 8) as f32 * (1.0 / (1u32 << 24) as f32)
+    }
+
+    // Uniform in [0, n).
+    pub fn gen_range(&mut self, n: usize) -> usize {
+        ((self.drand48() * n as f32) as usize).min(n.saturating_sub(1))
+    }
+}
+
+// SplitMix64 finalizer.
+pub fn mix(mut z: u64) -> u64 {
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
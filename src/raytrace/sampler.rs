@@ -0,0 +1,387 @@
+use super::rng::{mix, Rng};
+
+// Supplies the sample dimensions of one path. Every path draws its dimensions in the same
+// order (pixel, lens, time, then a fixed block per bounce), so dimension `d` of all samples
+// in a pixel is well distributed.
+pub trait Sampler: Send {
+    // Positions the sampler at sample `index` of pixel (x, y) and restarts the dimensions.
+    fn start_pixel_sample(&mut self, x: usize, y: usize, index: usize);
+    fn get_1d(&mut self) -> f32;
+    fn get_2d(&mut self) -> (f32, f32);
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SamplerKind {
+    Independent,
+    Stratified,
+    Halton,
+    Sobol,
+}
+
+impl SamplerKind {
+    pub const NAMES: &'static [&'static str] = &["independent", "stratified", "halton", "sobol"];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "independent" => Some(SamplerKind::Independent),
+            "stratified" => Some(SamplerKind::Stratified),
+            "halton" => Some(SamplerKind::Halton),
+            "sobol" => Some(SamplerKind::Sobol),
+            _ => None,
+        }
+    }
+
+    // A sampler for `samples` samples per pixel.
+    pub fn create(self, samples: usize, seed: u64) -> Box<dyn Sampler> {
+        match self {
+            SamplerKind::Independent => Box::new(IndependentSampler::new(seed)),
+            SamplerKind::Stratified => Box::new(StratifiedSampler::new(samples, seed)),
+            SamplerKind::Halton => Box::new(HaltonSampler::new(seed)),
+            SamplerKind::Sobol => Box::new(SobolSampler::new(seed)),
+        }
+    }
+}
+
+// Uniform random numbers, the reference the other samplers are compared against.
+pub struct IndependentSampler {
+    seed: u64,
+    rng: Rng,
+}
+
+impl IndependentSampler {
+    pub fn new(seed: u64) -> Self {
+        IndependentSampler {
+            seed,
+            rng: Rng::new(seed),
+        }
+    }
+}
+
+impl Sampler for IndependentSampler {
+    fn start_pixel_sample(&mut self, x: usize, y: usize, index: usize) {
+        self.rng = Rng::for_sample(self.seed, x, y, index);
+    }
+    fn get_1d(&mut self) -> f32 {
+        self.rng.drand48()
+    }
+    fn get_2d(&mut self) -> (f32, f32) {
+        (self.rng.drand48(), self.rng.drand48())
+    }
+}
+
+// Jittered strata, shuffled independently per dimension so the dimensions do not correlate.
+// 2D dimensions use the smallest grid with at least `samples` cells.
+pub struct StratifiedSampler {
+    samples: usize,
+    seed: u64,
+    pixel: u64,
+    index: usize,
+    dim: u64,
+}
+
+impl StratifiedSampler {
+    pub fn new(samples: usize, seed: u64) -> Self {
+        StratifiedSampler {
+            samples: samples.max(1),
+            seed,
+            pixel: 0,
+            index: 0,
+            dim: 0,
+        }
+    }
+
+    fn next_dimension(&mut self) -> (u32, Rng) {
+        let key = hash(&[self.seed, self.pixel, self.dim]);
+        self.dim += 1;
+        let jitter = Rng::with_stream(key, self.index as u64);
+        (key as u32, jitter)
+    }
+}
+
+impl Sampler for StratifiedSampler {
+    fn start_pixel_sample(&mut self, x: usize, y: usize, index: usize) {
+        self.pixel = pixel_key(x, y);
+        self.index = index;
+        self.dim = 0;
+    }
+    fn get_1d(&mut self) -> f32 {
+        let n = self.samples as u32;
+        let (key, mut jitter) = self.next_dimension();
+        let stratum = permute(self.index as u32 % n, n, key);
+        ((stratum as f32 + jitter.drand48()) / n as f32).min(ONE_MINUS_EPSILON)
+    }
+    fn get_2d(&mut self) -> (f32, f32) {
+        let nx = (self.samples as f32).sqrt().ceil() as u32;
+        let ny = (self.samples as u32).div_ceil(nx);
+        let (key, mut jitter) = self.next_dimension();
+        let stratum = permute(self.index as u32 % (nx * ny), nx * ny, key);
+        let u = ((stratum % nx) as f32 + jitter.drand48()) / nx as f32;
+        let v = ((stratum / nx) as f32 + jitter.drand48()) / ny as f32;
+        (u.min(ONE_MINUS_EPSILON), v.min(ONE_MINUS_EPSILON))
+    }
+}
+
+// Halton points, decorrelated between pixels with a random toroidal shift per dimension.
+// Dimensions past the prime table fall back to independent samples.
+pub struct HaltonSampler {
+    seed: u64,
+    pixel: u64,
+    index: u64,
+    dim: usize,
+}
+
+impl HaltonSampler {
+    pub fn new(seed: u64) -> Self {
+        HaltonSampler {
+            seed,
+            pixel: 0,
+            index: 0,
+            dim: 0,
+        }
+    }
+}
+
+impl Sampler for HaltonSampler {
+    fn start_pixel_sample(&mut self, x: usize, y: usize, index: usize) {
+        self.pixel = pixel_key(x, y);
+        self.index = index as u64;
+        self.dim = 0;
+    }
+    fn get_1d(&mut self) -> f32 {
+        let dim = self.dim;
+        self.dim += 1;
+        let key = hash(&[self.seed, self.pixel, dim as u64]);
+        if dim >= PRIMES.len() {
+            return Rng::with_stream(key, self.index).drand48();
+        }
+        let shift = Rng::new(key).drand48();
+        let v = radical_inverse(PRIMES[dim], self.index) + shift;
+        (if v >= 1.0 { v - 1.0 } else { v }).min(ONE_MINUS_EPSILON)
+    }
+    fn get_2d(&mut self) -> (f32, f32) {
+        (self.get_1d(), self.get_1d())
+    }
+}
+
+// Owen-scrambled Sobol points. Every dimension (or pair) is padded from the first two Sobol
+// dimensions with its own shuffle and scramble, which needs no direction number tables and
+// stays well distributed however many dimensions a path uses.
+pub struct SobolSampler {
+    seed: u64,
+    pixel: u64,
+    index: u32,
+    dim: u64,
+}
+
+impl SobolSampler {
+    pub fn new(seed: u64) -> Self {
+        SobolSampler {
+            seed,
+            pixel: 0,
+            index: 0,
+            dim: 0,
+        }
+    }
+
+    fn next_dimension(&mut self) -> (u32, u64) {
+        let key = hash(&[self.seed, self.pixel, self.dim]);
+        self.dim += 1;
+        (nested_uniform_scramble(self.index, key as u32), key)
+    }
+}
+
+impl Sampler for SobolSampler {
+    fn start_pixel_sample(&mut self, x: usize, y: usize, index: usize) {
+        self.pixel = pixel_key(x, y);
+        self.index = index as u32;
+        self.dim = 0;
+    }
+    fn get_1d(&mut self) -> f32 {
+        let (i, key) = self.next_dimension();
+        to_unit_float(nested_uniform_scramble(
+            i.reverse_bits(),
+            (key >> 32) as u32,
+        ))
+    }
+    fn get_2d(&mut self) -> (f32, f32) {
+        let (i, key) = self.next_dimension();
+        let key2 = mix(key);
+        (
+            to_unit_float(nested_uniform_scramble(
+                i.reverse_bits(),
+                (key >> 32) as u32,
+            )),
+            to_unit_float(nested_uniform_scramble(sobol_1(i), key2 as u32)),
+        )
+    }
+}
+
+const ONE_MINUS_EPSILON: f32 = 1.0 - f32::EPSILON / 2.0;
+
+const PRIMES: [u64; 32] = [
+    2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47, 53, 59, 61, 67, 71, 73, 79, 83, 89, 97,
+    101, 103, 107, 109, 113, 127, 131,
+];
+
+fn pixel_key(x: usize, y: usize) -> u64 {
+    ((y as u64) << 32) | x as u64
+}
+
+fn hash(values: &[u64]) -> u64 {
+    values
+        .iter()
+        .fold(0x9e37_79b9_7f4a_7c15, |h, &v| mix(h ^ mix(v)))
+}
+
+fn to_unit_float(v: u32) -> f32 {
+    (v >> 8) as f32 * (1.0 / (1u32 << 24) as f32)
+}
+
+fn radical_inverse(base: u64, mut i: u64) -> f32 {
+    let inv_base = 1.0 / base as f64;
+    let mut inv = 1.0;
+    let mut reversed = 0.0;
+    while i > 0 {
+        inv *= inv_base;
+        reversed += (i % base) as f64 * inv;
+        i /= base;
+    }
+    reversed as f32
+}
+
+// Second Sobol dimension, in bit-reversed order like `reverse_bits` is for the first.
+fn sobol_1(mut i: u32) -> u32 {
+    let mut v = 1u32 << 31;
+    let mut r = 0;
+    while i != 0 {
+        if i & 1 != 0 {
+            r ^= v;
+        }
+        i >>= 1;
+        v ^= v >> 1;
+    }
+    r
+}
+
+// Burley 2020, "Practical Hash-based Owen Scrambling".
+fn laine_karras_permutation(mut x: u32, seed: u32) -> u32 {
+    x = x.wrapping_add(seed);
+    x ^= x.wrapping_mul(0x6c50_b47c);
+    x ^= x.wrapping_mul(0xb82f_1e52);
+    x ^= x.wrapping_mul(0xc7af_e638);
+    x ^= x.wrapping_mul(0x8d22_f6e6);
+    x
+}
+
+fn nested_uniform_scramble(x: u32, seed: u32) -> u32 {
+    laine_karras_permutation(x.reverse_bits(), seed).reverse_bits()
+}
+
+// Element `i` of a pseudo-random permutation of 0..n, Kensler 2013.
+fn permute(mut i: u32, n: u32, p: u32) -> u32 {
+    let mut w = n - 1;
+    w |= w >> 1;
+    w |= w >> 2;
+    w |= w >> 4;
+    w |= w >> 8;
+    w |= w >> 16;
+    loop {
+        i ^= p;
+        i = i.wrapping_mul(0xe170_893d);
+        i ^= p >> 16;
+        i ^= (i & w) >> 4;
+        i ^= p >> 8;
+        i = i.wrapping_mul(0x0929_eb3f);
+        i ^= p >> 23;
+        i ^= (i & w) >> 1;
+        i = i.wrapping_mul(1 | p >> 27);
+        i = i.wrapping_mul(0x6935_fa69);
+        i ^= (i & w) >> 11;
+        i = i.wrapping_mul(0x74dc_b303);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0x9e50_1cc3);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0xc860_a3df);
+        i &= w;
+        i ^= i >> 5;
+        if i < n {
+            break;
+        }
+    }
+    i.wrapping_add(p) % n
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The first `dims` 1D and 2D dimensions of each sample of pixel (x, y).
+    fn draw(
+        sampler: &mut dyn Sampler,
+        x: usize,
+        y: usize,
+        samples: usize,
+        dims: usize,
+    ) -> Vec<f32> {
+        let mut values = Vec::new();
+        for index in 0..samples {
+            sampler.start_pixel_sample(x, y, index);
+            for _ in 0..dims {
+                values.push(sampler.get_1d());
+                let (u, v) = sampler.get_2d();
+                values.push(u);
+                values.push(v);
+            }
+        }
+        values
+    }
+
+    #[test]
+    fn samples_lie_in_the_unit_interval() {
+        for name in SamplerKind::NAMES {
+            let mut sampler = SamplerKind::from_name(name).unwrap().create(64, 3);
+            for &(x, y) in &[(0, 0), (17, 5), (4095, 4095)] {
+                for v in draw(sampler.as_mut(), x, y, 64, 40) {
+                    assert!((0.0..1.0).contains(&v), "{} gave {}", name, v);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn same_seed_repeats_and_other_seeds_differ() {
+        for name in SamplerKind::NAMES {
+            let kind = SamplerKind::from_name(name).unwrap();
+            let a = draw(kind.create(16, 1).as_mut(), 3, 7, 16, 8);
+            let b = draw(kind.create(16, 1).as_mut(), 3, 7, 16, 8);
+            let c = draw(kind.create(16, 2).as_mut(), 3, 7, 16, 8);
+            assert_eq!(a, b, "{:?}", kind);
+            assert_ne!(a, c, "{:?}", kind);
+            // Samples do not depend on the order they are drawn in.
+            let mut sampler = kind.create(16, 1);
+            let last = draw(sampler.as_mut(), 3, 7, 16, 8).split_off(15 * 24);
+            draw(sampler.as_mut(), 9, 9, 4, 8);
+            sampler.start_pixel_sample(3, 7, 15);
+            assert_eq!(sampler.get_1d(), last[0], "{:?}", kind);
+        }
+    }
+
+    #[test]
+    fn sixteen_samples_are_stratified() {
+        for kind in &[SamplerKind::Stratified, SamplerKind::Sobol] {
+            let mut sampler = kind.create(16, 5);
+            let values = draw(sampler.as_mut(), 2, 9, 16, 8);
+            for dim in 0..8 {
+                let mut strata = [0; 16];
+                let mut cells = [0; 16];
+                for sample in values.chunks(24) {
+                    let (w, u, v) = (sample[3 * dim], sample[3 * dim + 1], sample[3 * dim + 2]);
+                    strata[(w * 16.0) as usize] += 1;
+                    cells[(v * 4.0) as usize * 4 + (u * 4.0) as usize] += 1;
+                }
+                assert_eq!(strata, [1; 16], "{:?} dimension {}", kind, dim);
+                assert_eq!(cells, [1; 16], "{:?} dimension {}", kind, dim);
+            }
+        }
+    }
+}
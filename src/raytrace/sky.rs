@@ -0,0 +1,383 @@
+use super::distribution::Distribution2D;
+use super::environment::Environment;
+use super::skymap::{equirectangular_direction, equirectangular_uv};
+use super::tonemap::luminance;
+use super::vec::{onb, Vec3};
+use std::f32::consts::PI;
+
+// Angular radius of the sun as seen from the earth.
+const SUN_RADIUS: f32 = 0.004_65;
+// Luminance of the sun above the atmosphere, in kcd/m^2 like the sky model.
+const SUN_LUMINANCE: f32 = 2.0e6;
+// Scene units per kcd/m^2, which brings a white surface under the noon sun to about one.
+const UNITS_PER_KCD: f32 = 0.025;
+// Resolution of the table the sky is importance sampled with.
+const TABLE_WIDTH: usize = 128;
+const TABLE_HEIGHT: usize = 64;
+
+#[derive(Clone, Copy, Debug)]
+pub struct SkySettings {
+    // Degrees above the horizon, from 0 to 90.
+    pub sun_elevation: f32,
+    // Degrees around the vertical, from +x towards +z.
+    pub sun_azimuth: f32,
+    // Haziness of the atmosphere, from 2 for a clear sky to 10 for a hazy one.
+    pub turbidity: f32,
+    // Reflectance of the ground below the horizon.
+    pub ground_albedo: Vec3,
+}
+
+impl Default for SkySettings {
+    fn default() -> Self {
+        SkySettings {
+            sun_elevation: 45.0,
+            sun_azimuth: 0.0,
+            turbidity: 3.0,
+            ground_albedo: Vec3::new(0.3, 0.3, 0.3),
+        }
+    }
+}
+
+// Daylight from the analytic model of Preetham, Shirley and Smits, "A Practical Analytic Model
+// for Daylight" (1999), with a sun disk attenuated by the same atmosphere. The ground reflects
+// the light it receives diffusely. Samples are split between the sun and a table of the sky.
+pub struct SunSky {
+    model: SkyModel,
+    sun_radiance: Vec3,
+    cos_sun_radius: f32,
+    ground_radiance: Vec3,
+    // Intensity and tint every lookup is multiplied by.
+    scale: Vec3,
+    // Chance of sampling the sun rather than the sky.
+    sun_probability: f32,
+    distribution: Distribution2D,
+}
+
+impl SunSky {
+    pub fn new(settings: SkySettings) -> Self {
+        let t = settings.turbidity;
+        let elevation = settings.sun_elevation.clamp(0.0, 90.0).to_radians();
+        let azimuth = settings.sun_azimuth.to_radians();
+        let sun_direction = Vec3::new(
+            elevation.cos() * azimuth.cos(),
+            elevation.sin(),
+            elevation.cos() * azimuth.sin(),
+        );
+        let theta_s = PI / 2.0 - elevation;
+
+        let perez = [
+            [
+                0.1787 * t - 1.4630,
+                -0.3554 * t + 0.4275,
+                -0.0227 * t + 5.3251,
+                0.1206 * t - 2.5771,
+                -0.0670 * t + 0.3703,
+            ],
+            [
+                -0.0193 * t - 0.2592,
+                -0.0665 * t + 0.0008,
+                -0.0004 * t + 0.2125,
+                -0.0641 * t - 0.8989,
+                -0.0033 * t + 0.0452,
+            ],
+            [
+                -0.0167 * t - 0.2608,
+                -0.0950 * t + 0.0092,
+                -0.0079 * t + 0.2102,
+                -0.0441 * t - 1.6537,
+                -0.0109 * t + 0.0529,
+            ],
+        ];
+        let chi = (4.0 / 9.0 - t / 120.0) * (PI - 2.0 * theta_s);
+        let zenith_y = (4.0453 * t - 4.9710) * chi.tan() - 0.2155 * t + 2.4192;
+        let cubic =
+            |c: [f32; 4]| c[0] * theta_s.powi(3) + c[1] * theta_s.powi(2) + c[2] * theta_s + c[3];
+        let zenith_x = t * t * cubic([0.00166, -0.00375, 0.00209, 0.0])
+            + t * cubic([-0.02903, 0.06377, -0.03202, 0.00394])
+            + cubic([0.11693, -0.21196, 0.06052, 0.25886]);
+        let zenith_chroma_y = t * t * cubic([0.00275, -0.00610, 0.00317, 0.0])
+            + t * cubic([-0.04214, 0.08970, -0.04153, 0.00516])
+            + cubic([0.15346, -0.26756, 0.06670, 0.26688]);
+        let zenith = [
+            zenith_y.max(0.0) / perez_function(&perez[0], 1.0, theta_s),
+            zenith_x / perez_function(&perez[1], 1.0, theta_s),
+            zenith_chroma_y / perez_function(&perez[2], 1.0, theta_s),
+        ];
+        let model = SkyModel {
+            sun_direction,
+            perez,
+            zenith,
+        };
+        let sun_radiance = UNITS_PER_KCD * SUN_LUMINANCE * sun_transmittance(theta_s, t);
+        let cos_sun_radius = SUN_RADIUS.cos();
+
+        // Light falling on the ground, from the sun and from the sky above, integrated over
+        // the table's cells, which also weigh the sky for sampling.
+        let sun_solid_angle = 2.0 * PI * (1.0 - cos_sun_radius);
+        let mut irradiance = sun_radiance * sun_solid_angle * sun_direction.y();
+        let mut func = vec![0.0; TABLE_WIDTH * TABLE_HEIGHT];
+        let cell = 2.0 * PI * PI / (TABLE_WIDTH * TABLE_HEIGHT) as f32;
+        for (i, f) in func.iter_mut().enumerate() {
+            let u = ((i % TABLE_WIDTH) as f32 + 0.5) / TABLE_WIDTH as f32;
+            let v = ((i / TABLE_WIDTH) as f32 + 0.5) / TABLE_HEIGHT as f32;
+            let (direction, sin_theta) = equirectangular_direction(u, v);
+            if direction.y() > 0.0 {
+                let radiance = model.radiance(direction);
+                irradiance += radiance * direction.y() * sin_theta * cell;
+                *f = luminance(radiance) * sin_theta;
+            }
+        }
+        let ground_radiance = settings.ground_albedo * irradiance / PI;
+        let ground = luminance(ground_radiance);
+        for (i, f) in func.iter_mut().enumerate() {
+            if i / TABLE_WIDTH >= TABLE_HEIGHT / 2 {
+                let v = ((i / TABLE_WIDTH) as f32 + 0.5) / TABLE_HEIGHT as f32;
+                *f = ground * (v * PI).sin();
+            }
+        }
+
+        // Samples go to the sun in proportion to its share of the power.
+        let sun_power = luminance(sun_radiance) * sun_solid_angle;
+        let sky_power = func.iter().sum::<f32>() * cell;
+        SunSky {
+            model,
+            sun_radiance,
+            cos_sun_radius,
+            ground_radiance,
+            scale: Vec3::new(1.0, 1.0, 1.0),
+            sun_probability: (sun_power / (sun_power + sky_power).max(1e-6)).clamp(0.1, 0.9),
+            distribution: Distribution2D::new(&func, TABLE_WIDTH, TABLE_HEIGHT),
+        }
+    }
+
+    // Multiplies the radiance by `scale`, an intensity times a tint.
+    pub fn with_scale(mut self, scale: Vec3) -> Self {
+        self.scale = scale;
+        self
+    }
+
+    fn sun_pdf(&self, direction: Vec3) -> f32 {
+        if Vec3::dot(&direction, &self.model.sun_direction) >= self.cos_sun_radius {
+            1.0 / (2.0 * PI * (1.0 - self.cos_sun_radius))
+        } else {
+            0.0
+        }
+    }
+
+    fn table_pdf(&self, direction: Vec3) -> f32 {
+        let (u, v) = equirectangular_uv(direction);
+        let sin_theta = (v * PI).sin();
+        if sin_theta <= 0.0 {
+            return 0.0;
+        }
+        self.distribution.pdf(u, v) / (2.0 * PI * PI * sin_theta)
+    }
+}
+
+impl Environment for SunSky {
+    fn radiance(&self, direction: Vec3) -> Vec3 {
+        let direction = direction.normalize();
+        if direction.y() < 0.0 {
+            return self.scale * self.ground_radiance;
+        }
+        let mut radiance = self.model.radiance(direction);
+        if Vec3::dot(&direction, &self.model.sun_direction) >= self.cos_sun_radius {
+            radiance += self.sun_radiance;
+        }
+        self.scale * radiance
+    }
+
+    fn is_samplable(&self) -> bool {
+        true
+    }
+
+    // The first sample dimension picks the sun or the sky and is then reused for it.
+    fn sample(&self, u: (f32, f32)) -> Vec3 {
+        let p = self.sun_probability;
+        if u.0 < p {
+            // Uniformly within the cone of the sun disk.
+            let cos_theta = 1.0 - (u.0 / p) * (1.0 - self.cos_sun_radius);
+            let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+            let phi = 2.0 * PI * u.1;
+            let (a, b, w) = onb(self.model.sun_direction);
+            sin_theta * phi.cos() * a + sin_theta * phi.sin() * b + cos_theta * w
+        } else {
+            let u0 = ((u.0 - p) / (1.0 - p)).min(1.0 - f32::EPSILON);
+            let ((u, v), _) = self.distribution.sample((u0, u.1));
+            equirectangular_direction(u, v).0
+        }
+    }
+
+    fn pdf(&self, direction: Vec3) -> f32 {
+        let direction = direction.normalize();
+        self.sun_probability * self.sun_pdf(direction)
+            + (1.0 - self.sun_probability) * self.table_pdf(direction)
+    }
+}
+
+// The sky without the sun.
+struct SkyModel {
+    sun_direction: Vec3,
+    // Perez coefficients A to E of luminance Y and chromaticities x and y.
+    perez: [[f32; 5]; 3],
+    // Zenith values of Y, x and y divided by the Perez function at the zenith.
+    zenith: [f32; 3],
+}
+
+impl SkyModel {
+    // Radiance from a normalized direction above the horizon.
+    fn radiance(&self, direction: Vec3) -> Vec3 {
+        let cos_theta = direction.y().max(1e-3);
+        let gamma = Vec3::dot(&direction, &self.sun_direction)
+            .clamp(-1.0, 1.0)
+            .acos();
+        let value = |i: usize| self.zenith[i] * perez_function(&self.perez[i], cos_theta, gamma);
+        let (luminance, x, y) = (value(0), value(1), value(2));
+        UNITS_PER_KCD * xyy_to_rgb(x, y, luminance)
+    }
+}
+
+// Perez et al.'s sky distribution for the zenith angle `theta` and the angle `gamma` to the sun.
+fn perez_function(c: &[f32; 5], cos_theta: f32, gamma: f32) -> f32 {
+    (1.0 + c[0] * (c[1] / cos_theta).exp())
+        * (1.0 + c[2] * (c[3] * gamma).exp() + c[4] * gamma.cos() * gamma.cos())
+}
+
+// Fraction of sunlight passing through the atmosphere at red, green and blue wavelengths,
+// through Rayleigh and aerosol scattering, following Preetham et al.'s appendix.
+fn sun_transmittance(theta_s: f32, turbidity: f32) -> Vec3 {
+    let air_mass =
+        1.0 / (theta_s.cos() + 0.15 * (93.885 - theta_s.to_degrees()).powf(-1.253)).max(1e-3);
+    let beta = 0.04608 * turbidity - 0.04586;
+    let channel = |lambda: f32| {
+        let rayleigh = (-0.008735 * lambda.powf(-4.08) * air_mass).exp();
+        let aerosol = (-beta * lambda.powf(-1.3) * air_mass).exp();
+        rayleigh * aerosol
+    };
+    // Wavelengths in micrometres.
+    Vec3::new(channel(0.68), channel(0.55), channel(0.44))
+}
+
+// CIE xyY to linear sRGB.
+fn xyy_to_rgb(x: f32, y: f32, luminance: f32) -> Vec3 {
+    if y <= 0.0 {
+        return Vec3::new(0.0, 0.0, 0.0);
+    }
+    let big_x = x / y * luminance;
+    let big_z = (1.0 - x - y) / y * luminance;
+    Vec3::new(
+        (3.2406 * big_x - 1.5372 * luminance - 0.4986 * big_z).max(0.0),
+        (-0.9689 * big_x + 1.8758 * luminance + 0.0415 * big_z).max(0.0),
+        (0.0557 * big_x - 0.2040 * luminance + 1.0570 * big_z).max(0.0),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::rng::Rng;
+    use super::*;
+
+    fn sky() -> SunSky {
+        SunSky::new(SkySettings {
+            sun_elevation: 30.0,
+            sun_azimuth: 70.0,
+            ..SkySettings::default()
+        })
+    }
+
+    fn uniform_sphere(rng: &mut Rng) -> Vec3 {
+        let z = 1.0 - 2.0 * rng.drand48();
+        let r = (1.0 - z * z).max(0.0).sqrt();
+        let phi = 2.0 * PI * rng.drand48();
+        Vec3::new(r * phi.cos(), r * phi.sin(), z)
+    }
+
+    #[test]
+    fn samples_have_a_density() {
+        let sky = sky();
+        let mut rng = Rng::new(3);
+        let mut sun = 0;
+        for _ in 0..20_000 {
+            let direction = sky.sample((rng.drand48(), rng.drand48()));
+            let pdf = sky.pdf(direction);
+            assert!(pdf.is_finite() && pdf > 0.0, "{:?}", direction);
+            if sky.sun_pdf(direction) > 0.0 {
+                sun += 1;
+            }
+        }
+        let expected = sky.sun_probability * 20_000.0;
+        assert!((sun as f32 - expected).abs() < 0.05 * expected, "{}", sun);
+    }
+
+    // The sun's share is checked over a cone around it, as uniform samples of the whole sphere
+    // would hardly ever hit it.
+    #[test]
+    fn pdf_integrates_to_one_over_the_sphere() {
+        let sky = sky();
+        let mut rng = Rng::new(5);
+        let n = 200_000;
+        let table = (0..n)
+            .map(|_| sky.table_pdf(uniform_sphere(&mut rng)) as f64)
+            .sum::<f64>()
+            / n as f64
+            * 4.0
+            * std::f64::consts::PI;
+        assert!((table - 1.0).abs() < 0.02, "{}", table);
+
+        let cos_max = (2.0 * SUN_RADIUS).cos();
+        let (a, b, w) = onb(sky.model.sun_direction);
+        let sun = (0..n)
+            .map(|_| {
+                let cos_theta = 1.0 - rng.drand48() * (1.0 - cos_max);
+                let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+                let phi = 2.0 * PI * rng.drand48();
+                let d = sin_theta * phi.cos() * a + sin_theta * phi.sin() * b + cos_theta * w;
+                sky.sun_pdf(d) as f64
+            })
+            .sum::<f64>()
+            / n as f64
+            * 2.0
+            * std::f64::consts::PI
+            * (1.0 - cos_max as f64);
+        assert!((sun - 1.0).abs() < 0.02, "{}", sun);
+    }
+
+    #[test]
+    fn the_sun_disk_carries_the_sun_radiance() {
+        let sky = sky();
+        let sun = sky.model.sun_direction;
+        assert!(sky.radiance(sun).g() >= sky.sun_radiance.g());
+        let (a, _, _) = onb(sun);
+        let outside = (sun + 3.0 * SUN_RADIUS * a).normalize();
+        assert!(sky.radiance(outside).g() < 0.01 * sky.sun_radiance.g());
+        let scaled = sky.with_scale(Vec3::new(2.0, 2.0, 2.0));
+        assert!(scaled.radiance(sun).g() >= 2.0 * scaled.sun_radiance.g());
+    }
+
+    #[test]
+    fn radiance_is_finite_and_non_negative() {
+        let mut rng = Rng::new(9);
+        for &turbidity in &[1.7, 3.0, 6.0, 10.0] {
+            for &sun_elevation in &[0.0, 1.0, 15.0, 45.0, 89.0, 90.0] {
+                let sky = SunSky::new(SkySettings {
+                    sun_elevation,
+                    turbidity,
+                    ..SkySettings::default()
+                });
+                let sun = sky.model.sun_direction;
+                for direction in (0..500).map(|_| uniform_sphere(&mut rng)).chain(Some(sun)) {
+                    let c = sky.radiance(direction);
+                    for v in &[c.r(), c.g(), c.b()] {
+                        assert!(
+                            v.is_finite() && *v >= 0.0,
+                            "{:?} at turbidity {}, elevation {}",
+                            c,
+                            turbidity,
+                            sun_elevation
+                        );
+                    }
+                }
+            }
+        }
+    }
+}
@@ -1,42 +1,570 @@
 extern crate image;
 
-use super::ray::Ray;
+use super::distribution::Distribution2D;
+use super::environment::Environment;
+use super::exr;
+use super::matrix::Matrix44;
+use super::tonemap::{luminance, srgb_eotf};
 use super::vec::Vec3;
 use std::f32::consts::PI;
 use std::fs::File;
-use std::io::BufReader;
+use std::io::{self, BufReader};
+use std::path::Path;
 
+// Equirectangular environment map of any resolution. Lookups are bilinear, wrapping around in
+// longitude and clamped at the poles.
 pub struct IBLSkyMap {
-    hdr_image: Vec<image::Rgb<f32>>,
+    hdr_image: Vec<Vec3>,
+    width: usize,
     height: usize,
-    longitude_offset: f32,
+    // Rotation from map space into world space, and its inverse.
+    to_world: Matrix44,
+    to_map: Matrix44,
+    // Intensity and tint every lookup is multiplied by.
+    scale: Vec3,
+    // Pixel luminance weighted by the solid angle of its row, for importance sampling.
+    distribution: Distribution2D,
 }
 
 impl IBLSkyMap {
-    pub fn new(path: &str, longitude_offset: f32) -> Self {
-        let image_file = File::open(path).unwrap();
-        let decoder = image::hdr::HDRDecoder::new(BufReader::new(image_file)).unwrap();
-        let height = decoder.metadata().height as usize;
-        let hdr_image = decoder.read_image_hdr().unwrap();
+    // Loads a Radiance .hdr, OpenEXR .exr or sRGB .png/.jpg environment. The layout comes from
+    // the aspect ratio: 1:1 is an angular light probe, 4:3 and 3:4 are cube-map crosses, 6:1
+    // and 1:6 are strips of the six cube faces in +x, -x, +y, -y, +z, -z order, and anything
+    // else is equirectangular. A path containing `{face}` names six separate cube faces, with
+    // `{face}` standing for px, nx, py, ny, pz and nz.
+    pub fn new(path: &str) -> io::Result<Self> {
+        let image = if path.contains("{face}") {
+            read_faces(path)?
+        } else {
+            read_image(path)?
+        };
+        let image = match MapLayout::detect(image.width, image.height) {
+            MapLayout::Equirectangular => image,
+            layout => to_equirectangular(&image, layout),
+        };
+        Ok(IBLSkyMap::from_pixels(
+            image.pixels,
+            image.width,
+            image.height,
+        ))
+    }
+
+    // Map over `width` by `height` pixels in row order, top row first.
+    pub fn from_pixels(hdr_image: Vec<Vec3>, width: usize, height: usize) -> Self {
+        let func: Vec<f32> = (0..width * height)
+            .map(|i| {
+                let sin_theta = (PI * ((i / width) as f32 + 0.5) / height as f32).sin();
+                luminance(hdr_image[i]).max(0.0) * sin_theta
+            })
+            .collect();
         IBLSkyMap {
             hdr_image,
+            width,
             height,
-            longitude_offset,
+            to_world: Matrix44::identity(),
+            to_map: Matrix44::identity(),
+            scale: Vec3::new(1.0, 1.0, 1.0),
+            distribution: Distribution2D::new(&func, width, height),
+        }
+    }
+
+    // Rotates the map by the given rotation, which must not scale or translate.
+    pub fn with_rotation(mut self, rotation: Matrix44) -> Self {
+        self.to_world = rotation;
+        self.to_map = rotation.inverse();
+        self
+    }
+
+    // Rotation by the Euler angles in radians about the x, then y, then z axis. A rotation
+    // about y alone turns the map in longitude.
+    pub fn with_euler_rotation(self, angles: Vec3) -> Self {
+        self.with_rotation(
+            Matrix44::rotate_z(angles.z())
+                * Matrix44::rotate_y(angles.y())
+                * Matrix44::rotate_x(angles.x()),
+        )
+    }
+
+    // Multiplies the radiance by `scale`, an intensity times a tint.
+    pub fn with_scale(mut self, scale: Vec3) -> Self {
+        self.scale = scale;
+        self
+    }
+
+    // A copy blurred by about `degrees` of longitude, for a soft backdrop. Three box blurs
+    // approximate a Gaussian; rows are blurred by pixels, so the blur widens near the poles.
+    pub fn blurred(&self, degrees: f32) -> Self {
+        let (width, height) = (self.width, self.height);
+        let radius = (degrees / 360.0 * width as f32).round().max(0.0) as isize;
+        let mut pixels = self.hdr_image.clone();
+        for _ in 0..3 {
+            pixels = box_blur(&pixels, width, height, radius, true);
+            pixels = box_blur(&pixels, width, height, radius, false);
         }
+        let mut map = IBLSkyMap::from_pixels(pixels, width, height);
+        map.to_world = self.to_world;
+        map.to_map = self.to_map;
+        map.scale = self.scale;
+        map
+    }
+
+    // Image coordinates of a normalized world direction.
+    fn direction_to_uv(&self, dir: Vec3) -> (f32, f32) {
+        equirectangular_uv(self.to_map * dir)
+    }
+
+    // Inverse of `direction_to_uv`, with the sine of the polar angle.
+    fn uv_to_direction(&self, u: f32, v: f32) -> (Vec3, f32) {
+        let (dir, sin_theta) = equirectangular_direction(u, v);
+        (self.to_world * dir, sin_theta)
+    }
+
+    fn texel(&self, x: usize, y: usize) -> Vec3 {
+        self.hdr_image[y * self.width + x]
     }
 }
-pub fn radiance(map: &IBLSkyMap, r: Ray) -> Vec3 {
-    let dir = r.direction.normalize();
-    let theta = (dir.y()).acos();
+
+impl Environment for IBLSkyMap {
+    fn radiance(&self, direction: Vec3) -> Vec3 {
+        let (u, v) = self.direction_to_uv(direction.normalize());
+        // Texel centres sit at half-integer coordinates.
+        let x = u * self.width as f32 - 0.5;
+        let y = (v * self.height as f32 - 0.5).clamp(0.0, (self.height - 1) as f32);
+        let (fx, fy) = (x - x.floor(), y - y.floor());
+        let x0 = (x.floor() as isize).rem_euclid(self.width as isize) as usize;
+        let x1 = (x0 + 1) % self.width;
+        let y0 = y.floor() as usize;
+        let y1 = (y0 + 1).min(self.height - 1);
+        let top = (1.0 - fx) * self.texel(x0, y0) + fx * self.texel(x1, y0);
+        let bottom = (1.0 - fx) * self.texel(x0, y1) + fx * self.texel(x1, y1);
+        self.scale * ((1.0 - fy) * top + fy * bottom)
+    }
+
+    fn is_samplable(&self) -> bool {
+        true
+    }
+
+    fn sample(&self, u: (f32, f32)) -> Vec3 {
+        let ((u, v), _) = self.distribution.sample(u);
+        self.uv_to_direction(u, v).0
+    }
+
+    // The image maps to the sphere with a Jacobian of 2 pi^2 sin(theta).
+    fn pdf(&self, direction: Vec3) -> f32 {
+        let (u, v) = self.direction_to_uv(direction.normalize());
+        let sin_theta = (v * PI).sin();
+        if sin_theta <= 0.0 {
+            return 0.0;
+        }
+        self.distribution.pdf(u, v) / (2.0 * PI * PI * sin_theta)
+    }
+}
+
+// How an environment image covers the sphere.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MapLayout {
+    Equirectangular,
+    AngularProbe,
+    HorizontalCross,
+    VerticalCross,
+    HorizontalStrip,
+    VerticalStrip,
+}
+
+impl MapLayout {
+    pub fn detect(width: usize, height: usize) -> Self {
+        if width == height {
+            MapLayout::AngularProbe
+        } else if 3 * width == 4 * height {
+            MapLayout::HorizontalCross
+        } else if 4 * width == 3 * height {
+            MapLayout::VerticalCross
+        } else if width == 6 * height {
+            MapLayout::HorizontalStrip
+        } else if height == 6 * width {
+            MapLayout::VerticalStrip
+        } else {
+            MapLayout::Equirectangular
+        }
+    }
+
+    // Edge length of a cube face, and the face's column and row in the layout. The -z face of
+    // a vertical cross is upside down.
+    fn face_cell(self, width: usize, face: usize) -> (usize, usize, usize, bool) {
+        const CROSS: [(usize, usize); 6] = [(2, 1), (0, 1), (1, 0), (1, 2), (1, 1), (3, 1)];
+        match self {
+            MapLayout::HorizontalCross => (width / 4, CROSS[face].0, CROSS[face].1, false),
+            MapLayout::VerticalCross if face == 5 => (width / 3, 1, 3, true),
+            MapLayout::VerticalCross => (width / 3, CROSS[face].0, CROSS[face].1, false),
+            MapLayout::HorizontalStrip => (width / 6, face, 0, false),
+            _ => (width, 0, face, false),
+        }
+    }
+}
+
+struct Image {
+    pixels: Vec<Vec3>,
+    width: usize,
+    height: usize,
+}
+
+impl Image {
+    // Bilinear lookup at continuous pixel coordinates, clamped to the rectangle of `w` by `h`
+    // pixels at (x0, y0).
+    fn bilinear(&self, x: f32, y: f32, rect: (usize, usize, usize, usize)) -> Vec3 {
+        let (x0, y0, w, h) = rect;
+        let x = (x - 0.5).clamp(x0 as f32, (x0 + w - 1) as f32);
+        let y = (y - 0.5).clamp(y0 as f32, (y0 + h - 1) as f32);
+        let (fx, fy) = (x - x.floor(), y - y.floor());
+        let (ix, iy) = (x.floor() as usize, y.floor() as usize);
+        let (jx, jy) = ((ix + 1).min(x0 + w - 1), (iy + 1).min(y0 + h - 1));
+        let texel = |x: usize, y: usize| self.pixels[y * self.width + x];
+        let top = (1.0 - fx) * texel(ix, iy) + fx * texel(jx, iy);
+        let bottom = (1.0 - fx) * texel(ix, jy) + fx * texel(jx, jy);
+        (1.0 - fy) * top + fy * bottom
+    }
+}
+
+fn read_image(path: &str) -> io::Result<Image> {
+    let extension = Path::new(path)
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_ascii_lowercase());
+    let open = |err: io::Error| {
+        io::Error::new(
+            err.kind(),
+            format!("cannot read environment map '{}': {}", path, err),
+        )
+    };
+    let invalid = |err: image::ImageError| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("cannot read environment map '{}': {}", path, err),
+        )
+    };
+    let (pixels, width, height) = match extension.as_deref() {
+        Some("hdr") => {
+            let decoder =
+                image::hdr::HDRDecoder::new(BufReader::new(File::open(path).map_err(open)?))
+                    .map_err(invalid)?;
+            let width = decoder.metadata().width as usize;
+            let height = decoder.metadata().height as usize;
+            let pixels = decoder.read_image_hdr().map_err(invalid)?;
+            let pixels: Vec<Vec3> = pixels
+                .iter()
+                .map(|col| Vec3::new(col[0], col[1], col[2]))
+                .collect();
+            (pixels, width, height)
+        }
+        Some("exr") => exr::read(Path::new(path)).map_err(open)?,
+        // 8-bit images are sRGB encoded.
+        Some("png") | Some("jpg") | Some("jpeg") => {
+            let image = image::open(path).map_err(invalid)?.to_rgb();
+            let (width, height) = (image.width() as usize, image.height() as usize);
+            let pixels: Vec<Vec3> = image
+                .pixels()
+                .map(|p| {
+                    let c = |v: u8| srgb_eotf(v as f32 / 255.0);
+                    Vec3::new(c(p[0]), c(p[1]), c(p[2]))
+                })
+                .collect();
+            (pixels, width, height)
+        }
+        _ => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                "unsupported environment map format for '{}', expected .hdr, .exr, .png or .jpg",
+                path
+            ),
+            ))
+        }
+    };
+    if width == 0 || height == 0 || pixels.len() != width * height {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("environment map '{}' has no pixel data", path),
+        ));
+    }
+    Ok(Image {
+        pixels,
+        width,
+        height,
+    })
+}
+
+// Six square faces of the same size, put side by side as a horizontal strip.
+fn read_faces(pattern: &str) -> io::Result<Image> {
+    let faces = ["px", "nx", "py", "ny", "pz", "nz"]
+        .iter()
+        .map(|face| read_image(&pattern.replace("{face}", face)))
+        .collect::<io::Result<Vec<Image>>>()?;
+    let size = faces[0].width;
+    if faces.iter().any(|f| f.width != size || f.height != size) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "the cube faces of '{}' are not squares of one size",
+                pattern
+            ),
+        ));
+    }
+    let mut pixels = Vec::with_capacity(6 * size * size);
+    for y in 0..size {
+        for face in &faces {
+            pixels.extend_from_slice(&face.pixels[y * size..(y + 1) * size]);
+        }
+    }
+    Ok(Image {
+        pixels,
+        width: 6 * size,
+        height: size,
+    })
+}
+
+// Averages over 2 * radius + 1 pixels along rows, wrapping around, or along columns, clamped
+// at the top and bottom.
+fn box_blur(
+    pixels: &[Vec3],
+    width: usize,
+    height: usize,
+    radius: isize,
+    horizontal: bool,
+) -> Vec<Vec3> {
+    let n = (2 * radius + 1) as f32;
+    (0..width * height)
+        .map(|i| {
+            let (x, y) = ((i % width) as isize, (i / width) as isize);
+            let mut sum = Vec3::new(0.0, 0.0, 0.0);
+            for k in -radius..=radius {
+                let j = if horizontal {
+                    y as usize * width + (x + k).rem_euclid(width as isize) as usize
+                } else {
+                    (y + k).clamp(0, height as isize - 1) as usize * width + x as usize
+                };
+                sum += pixels[j];
+            }
+            sum / n
+        })
+        .collect()
+}
+
+// Equirectangular image coordinates in [0, 1)^2 of a normalized direction; v = 0 is straight
+// up.
+pub fn equirectangular_uv(dir: Vec3) -> (f32, f32) {
+    let theta = dir.y().clamp(-1.0, 1.0).acos();
     let phi = dir.z().atan2(dir.x());
-    let u = ((phi + PI + map.longitude_offset) / (2.0 * PI)) % 1.0;
-    let v = (theta / PI) % 1.0;
-    let height = map.height;
-    let width = map.height * 2;
-    let all = width * height;
-    let x = (width as f32 * u).floor() as usize;
-    let y = (height as f32 * v).floor() as usize;
-    let index = y * width + x;
-    let col = map.hdr_image[index % all];
-    Vec3::new(col[0], col[1], col[2])
+    let u = ((phi + PI) / (2.0 * PI)).rem_euclid(1.0);
+    let v = (theta / PI).min(1.0 - f32::EPSILON);
+    (u, v)
+}
+
+// Inverse of `equirectangular_uv`, with the sine of the polar angle.
+pub fn equirectangular_direction(u: f32, v: f32) -> (Vec3, f32) {
+    let theta = v * PI;
+    let phi = u * 2.0 * PI - PI;
+    let sin_theta = theta.sin();
+    let dir = Vec3::new(sin_theta * phi.cos(), theta.cos(), sin_theta * phi.sin());
+    (dir, sin_theta)
+}
+
+// Cube face of a direction, in +x, -x, +y, -y, +z, -z order, with the position on it in
+// [0, 1]^2 from the top left, following the OpenGL cube-map convention.
+fn cube_face(d: Vec3) -> (usize, f32, f32) {
+    let (ax, ay, az) = (d.x().abs(), d.y().abs(), d.z().abs());
+    let (face, s, t) = if ax >= ay && ax >= az {
+        if d.x() > 0.0 {
+            (0, -d.z() / ax, -d.y() / ax)
+        } else {
+            (1, d.z() / ax, -d.y() / ax)
+        }
+    } else if ay >= az {
+        if d.y() > 0.0 {
+            (2, d.x() / ay, d.z() / ay)
+        } else {
+            (3, d.x() / ay, -d.z() / ay)
+        }
+    } else if d.z() > 0.0 {
+        (4, d.x() / az, -d.y() / az)
+    } else {
+        (5, -d.x() / az, -d.y() / az)
+    };
+    (face, 0.5 * (s + 1.0), 0.5 * (t + 1.0))
+}
+
+// Resamples a light probe or cube map into an equirectangular image of similar detail.
+fn to_equirectangular(image: &Image, layout: MapLayout) -> Image {
+    let height = match layout {
+        MapLayout::AngularProbe => image.width,
+        _ => 2 * layout.face_cell(image.width, 0).0,
+    };
+    let width = 2 * height;
+    let pixels = (0..width * height)
+        .map(|i| {
+            let u = ((i % width) as f32 + 0.5) / width as f32;
+            let v = ((i / width) as f32 + 0.5) / height as f32;
+            let d = equirectangular_direction(u, v).0;
+            if layout == MapLayout::AngularProbe {
+                // Debevec's mapping: the centre looks down -z and the radius grows linearly
+                // with the angle from it, reaching the opposite direction at the rim.
+                let r = (d.x() * d.x() + d.y() * d.y()).sqrt();
+                let scale = if r > 0.0 {
+                    (-d.z()).clamp(-1.0, 1.0).acos() / (PI * r)
+                } else {
+                    0.0
+                };
+                let x = 0.5 * (d.x() * scale + 1.0) * image.width as f32;
+                let y = 0.5 * (1.0 - d.y() * scale) * image.height as f32;
+                image.bilinear(x, y, (0, 0, image.width, image.height))
+            } else {
+                let (face, s, t) = cube_face(d);
+                let (size, column, row, flipped) = layout.face_cell(image.width, face);
+                let (s, t) = if flipped { (1.0 - s, 1.0 - t) } else { (s, t) };
+                let (x0, y0) = (column * size, row * size);
+                image.bilinear(
+                    x0 as f32 + s * size as f32,
+                    y0 as f32 + t * size as f32,
+                    (x0, y0, size, size),
+                )
+            }
+        })
+        .collect();
+    Image {
+        pixels,
+        width,
+        height,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::rng::Rng;
+    use super::*;
+
+    // A dim gradient with one bright texel, rotated off the axes.
+    fn map() -> IBLSkyMap {
+        let (width, height) = (16, 8);
+        let mut pixels: Vec<Vec3> = (0..width * height)
+            .map(|i| Vec3::new(0.1, 0.2, 0.3) * (1.0 + (i % width) as f32 / width as f32))
+            .collect();
+        pixels[2 * width + 5] = Vec3::new(50.0, 40.0, 30.0);
+        IBLSkyMap::from_pixels(pixels, width, height).with_euler_rotation(Vec3::new(0.3, 1.1, -0.4))
+    }
+
+    fn uniform_sphere(rng: &mut Rng) -> Vec3 {
+        let z = 1.0 - 2.0 * rng.drand48();
+        let r = (1.0 - z * z).max(0.0).sqrt();
+        let phi = 2.0 * PI * rng.drand48();
+        Vec3::new(r * phi.cos(), r * phi.sin(), z)
+    }
+
+    #[test]
+    fn pdf_integrates_to_one_over_the_sphere() {
+        let map = map();
+        let mut rng = Rng::new(7);
+        let n = 200_000;
+        let sum: f64 = (0..n)
+            .map(|_| map.pdf(uniform_sphere(&mut rng)) as f64)
+            .sum();
+        let integral = sum / n as f64 * 4.0 * std::f64::consts::PI;
+        assert!((integral - 1.0).abs() < 0.02, "{}", integral);
+    }
+
+    #[test]
+    fn importance_sampling_agrees_with_uniform_sampling() {
+        let map = map();
+        let mut rng = Rng::new(11);
+        let n = 200_000;
+        let uniform: f64 = (0..n)
+            .map(|_| luminance(map.radiance(uniform_sphere(&mut rng))) as f64)
+            .sum::<f64>()
+            / n as f64
+            * 4.0
+            * std::f64::consts::PI;
+        // Samples that round onto a pole have no density and are dropped, as the renderer does.
+        let mut dropped = 0;
+        let mut sampled = 0.0;
+        for _ in 0..n {
+            let direction = map.sample((rng.drand48(), rng.drand48()));
+            let pdf = map.pdf(direction);
+            if pdf > 0.0 {
+                sampled += (luminance(map.radiance(direction)) / pdf) as f64;
+            } else {
+                dropped += 1;
+            }
+        }
+        let sampled = sampled / n as f64;
+        assert!(dropped < n / 10_000, "{} samples without density", dropped);
+        assert!(
+            (sampled / uniform - 1.0).abs() < 0.02,
+            "{} {}",
+            sampled,
+            uniform
+        );
+    }
+
+    fn texel_direction(x: usize, y: usize, width: usize, height: usize) -> Vec3 {
+        let u = (x as f32 + 0.5) / width as f32;
+        let v = (y as f32 + 0.5) / height as f32;
+        equirectangular_direction(u, v).0
+    }
+
+    fn close(a: Vec3, b: Vec3) -> bool {
+        (a - b).length() < 1e-3 * (1.0 + b.length())
+    }
+
+    #[test]
+    fn texel_centres_return_the_texel() {
+        let (width, height) = (16, 8);
+        let pixels: Vec<Vec3> = (0..width * height)
+            .map(|i| Vec3::new(i as f32, (i % 5) as f32, 1.0))
+            .collect();
+        let map = IBLSkyMap::from_pixels(pixels, width, height);
+        for y in 0..height {
+            for x in 0..width {
+                let c = map.radiance(texel_direction(x, y, width, height));
+                assert!(close(c, map.texel(x, y)), "{:?} at {}, {}", c, x, y);
+            }
+        }
+    }
+
+    #[test]
+    fn rotation_moves_the_map() {
+        let rotation = Matrix44::rotate_z(-0.4) * Matrix44::rotate_y(1.1) * Matrix44::rotate_x(0.3);
+        let map = map();
+        let bright = map.texel(5, 2);
+        let direction = texel_direction(5, 2, 16, 8);
+        assert!(close(map.radiance(rotation * direction), bright));
+        assert!(map.radiance(direction).r() < 1.0);
+        // A quarter turn about y moves the texel a quarter of the way around in longitude.
+        let turned = IBLSkyMap::from_pixels(map.hdr_image.clone(), 16, 8)
+            .with_euler_rotation(Vec3::new(0.0, PI / 2.0, 0.0));
+        let moved = (0..16)
+            .filter(|&x| turned.radiance(texel_direction(x, 2, 16, 8)).r() > 10.0)
+            .collect::<Vec<_>>();
+        assert_eq!(moved, vec![1]);
+    }
+
+    #[test]
+    fn unreadable_files_are_errors() {
+        let message = |path: &str| match IBLSkyMap::new(path) {
+            Ok(_) => panic!("'{}' loaded", path),
+            Err(err) => err.to_string(),
+        };
+        let missing = "no/such/sky.hdr";
+        assert!(message(missing).contains(missing));
+        assert!(message("sky.txt").contains("unsupported environment map format"));
+        for extension in &["hdr", "exr", "png"] {
+            let path = std::env::temp_dir().join(format!(
+                "skymap-{}-garbage.{}",
+                std::process::id(),
+                extension
+            ));
+            std::fs::write(&path, b"not an image at all").unwrap();
+            let path = path.to_str().unwrap().to_string();
+            let err = message(&path);
+            std::fs::remove_file(&path).unwrap();
+            assert!(err.contains(&path), "{}", err);
+        }
+    }
 }
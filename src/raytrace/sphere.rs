@@ -1,11 +1,14 @@
 use super::aabb::AABB;
 use super::hitable::{HitRecord, Hitable};
+use super::light::{area_to_solid_angle, random_in_cone, Light};
 use super::material::Material;
 use super::ray::Ray;
-use super::vec::{get_sphere_uv, Vec3};
+use super::vec::{get_sphere_uv, onb, random_unit_vector, Vec3};
 
+use std::f32::consts::PI;
 use std::sync::Arc;
 
+#[derive(Clone)]
 pub struct Sphere {
     pub center: Vec3,
     pub radius: f32,
@@ -42,6 +45,7 @@ impl Hitable for Sphere {
                     v,
                     p,
                     normal,
+                    geometric_normal: normal,
                     material: Arc::clone(&self.material),
                 });
             }
@@ -57,6 +61,7 @@ impl Hitable for Sphere {
                     v,
                     p,
                     normal,
+                    geometric_normal: normal,
                     material: Arc::clone(&self.material),
                 });
             }
@@ -69,4 +74,42 @@ impl Hitable for Sphere {
             max: self.center + Vec3::new(self.radius, self.radius, self.radius),
         })
     }
+    fn collect_lights(&self, lights: &mut Vec<Arc<dyn Light>>) {
+        if self.material.is_emissive() {
+            lights.push(Arc::new(self.clone()));
+        }
+    }
+    fn collect_materials(&self, materials: &mut Vec<Arc<dyn Material>>) {
+        materials.push(Arc::clone(&self.material));
+    }
+}
+
+impl Light for Sphere {
+    fn pdf_value(&self, origin: Vec3, direction: Vec3) -> f32 {
+        match self.hit(Ray::new(origin, direction, 0.0), 0.001, f32::MAX) {
+            Some(rec) => {
+                let distance_squared = (self.center - origin).squared_length();
+                let radius_squared = self.radius * self.radius;
+                if distance_squared <= radius_squared {
+                    let area = 4.0 * PI * radius_squared;
+                    return area_to_solid_angle(area, rec.t, direction, rec.normal);
+                }
+                let cos_theta_max = f32::sqrt(1.0 - radius_squared / distance_squared);
+                1.0 / (2.0 * PI * (1.0 - cos_theta_max))
+            }
+            None => 0.0,
+        }
+    }
+    fn random(&self, origin: Vec3, u: (f32, f32)) -> Vec3 {
+        let direction = self.center - origin;
+        let distance_squared = direction.squared_length();
+        let radius_squared = self.radius * self.radius;
+        if distance_squared <= radius_squared {
+            return self.center + self.radius * random_unit_vector(u) - origin;
+        }
+        let cos_theta_max = f32::sqrt(1.0 - radius_squared / distance_squared);
+        let d = random_in_cone(cos_theta_max, u);
+        let (u, v, w) = onb(direction);
+        d.x() * u + d.y() * v + d.z() * w
+    }
 }
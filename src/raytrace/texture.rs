@@ -1,4 +1,5 @@
 use super::perlin::Perlin;
+use super::rng::Rng;
 use super::vec::Vec3;
 
 pub trait Texture: Sync + Send {
@@ -52,9 +53,9 @@ pub struct NoiseTexture {
 }
 
 impl NoiseTexture {
-    pub fn new(scale: f32) -> Self {
+    pub fn new(scale: f32, rng: &mut Rng) -> Self {
         NoiseTexture {
-            noise: Perlin::new(),
+            noise: Perlin::new(rng),
             scale,
         }
     }
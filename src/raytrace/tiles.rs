@@ -0,0 +1,176 @@
+// Order in which the tiles of an image are handed to the render threads.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TileOrder {
+    // Left to right, top to bottom.
+    Scanline,
+    // Outwards from the center of the image, which usually holds the subject.
+    Spiral,
+    // Along a Hilbert curve, so consecutive tiles are mostly neighbours. The curve is laid over
+    // a power of two square and tiles outside the image are skipped, which leaves jumps when
+    // the grid is not square.
+    Hilbert,
+}
+
+impl TileOrder {
+    pub const NAMES: &'static [&'static str] = &["scanline", "spiral", "hilbert"];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "scanline" => Some(TileOrder::Scanline),
+            "spiral" => Some(TileOrder::Spiral),
+            "hilbert" => Some(TileOrder::Hilbert),
+            _ => None,
+        }
+    }
+}
+
+// Raster pixels [x0, x1) x [y0, y1).
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TileBounds {
+    pub x0: usize,
+    pub y0: usize,
+    pub x1: usize,
+    pub y1: usize,
+}
+
+impl TileBounds {
+    pub fn width(&self) -> usize {
+        self.x1 - self.x0
+    }
+
+    pub fn pixels(&self) -> usize {
+        (self.x1 - self.x0) * (self.y1 - self.y0)
+    }
+}
+
+// Tiles of at most `size` by `size` pixels covering a `width` by `height` image, in `order`.
+// An image without pixels has no tiles.
+pub fn tiles(width: usize, height: usize, size: usize, order: TileOrder) -> Vec<TileBounds> {
+    let size = size.max(1);
+    let nx = width.div_ceil(size);
+    let ny = height.div_ceil(size);
+    let grid = match order {
+        TileOrder::Scanline => (0..nx * ny).map(|i| (i % nx, i / nx)).collect(),
+        TileOrder::Spiral => spiral(nx, ny),
+        TileOrder::Hilbert => hilbert(nx, ny),
+    };
+    grid.into_iter()
+        .map(|(tx, ty)| TileBounds {
+            x0: tx * size,
+            y0: ty * size,
+            x1: ((tx + 1) * size).min(width),
+            y1: ((ty + 1) * size).min(height),
+        })
+        .collect()
+}
+
+// Walks right 1, down 1, left 2, up 2, right 3, ... from the center tile, skipping the steps
+// that fall outside the grid.
+fn spiral(nx: usize, ny: usize) -> Vec<(usize, usize)> {
+    let count = nx * ny;
+    if count == 0 {
+        return Vec::new();
+    }
+    let mut grid = Vec::with_capacity(count);
+    let (mut x, mut y) = (((nx - 1) / 2) as isize, ((ny - 1) / 2) as isize);
+    let directions = [(1, 0), (0, 1), (-1, 0), (0, -1)];
+    let mut leg = 0;
+    grid.push((x as usize, y as usize));
+    while grid.len() < count {
+        let (dx, dy) = directions[leg % 4];
+        for _ in 0..leg / 2 + 1 {
+            x += dx;
+            y += dy;
+            if x >= 0 && y >= 0 && (x as usize) < nx && (y as usize) < ny {
+                grid.push((x as usize, y as usize));
+            }
+        }
+        leg += 1;
+    }
+    grid
+}
+
+// Follows the curve over the smallest power of two square holding the grid and keeps the
+// tiles that exist.
+fn hilbert(nx: usize, ny: usize) -> Vec<(usize, usize)> {
+    let n = nx.max(ny).next_power_of_two();
+    (0..n * n)
+        .map(|d| hilbert_point(n, d))
+        .filter(|&(x, y)| x < nx && y < ny)
+        .collect()
+}
+
+// Point `d` along the Hilbert curve over an `n` by `n` grid.
+fn hilbert_point(n: usize, d: usize) -> (usize, usize) {
+    let (mut x, mut y) = (0, 0);
+    let mut t = d;
+    let mut s = 1;
+    while s < n {
+        let rx = 1 & (t / 2);
+        let ry = 1 & (t ^ rx);
+        if ry == 0 {
+            if rx == 1 {
+                x = s - 1 - x;
+                y = s - 1 - y;
+            }
+            std::mem::swap(&mut x, &mut y);
+        }
+        x += s * rx;
+        y += s * ry;
+        t /= 4;
+        s *= 2;
+    }
+    (x, y)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ORDERS: [TileOrder; 3] = [TileOrder::Scanline, TileOrder::Spiral, TileOrder::Hilbert];
+
+    #[test]
+    fn every_pixel_is_covered_once() {
+        for &(width, height, size) in &[(64, 64, 16), (100, 37, 16), (5, 300, 7), (1, 1, 32)] {
+            for &order in &ORDERS {
+                let mut covered = vec![0; width * height];
+                for tile in tiles(width, height, size, order) {
+                    assert!(tile.x1 <= width && tile.y1 <= height);
+                    for y in tile.y0..tile.y1 {
+                        for x in tile.x0..tile.x1 {
+                            covered[y * width + x] += 1;
+                        }
+                    }
+                }
+                assert!(
+                    covered.iter().all(|&c| c == 1),
+                    "{:?} over {}x{} in tiles of {}",
+                    order,
+                    width,
+                    height,
+                    size
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn empty_images_have_no_tiles() {
+        for &order in &ORDERS {
+            assert!(tiles(0, 10, 16, order).is_empty());
+            assert!(tiles(10, 0, 16, order).is_empty());
+            assert!(tiles(0, 0, 0, order).is_empty());
+        }
+    }
+
+    #[test]
+    fn hilbert_steps_to_neighbours_on_square_grids() {
+        let grid = hilbert(8, 8);
+        for pair in grid.windows(2) {
+            let (a, b) = (pair[0], pair[1]);
+            let distance =
+                (a.0 as isize - b.0 as isize).abs() + (a.1 as isize - b.1 as isize).abs();
+            assert_eq!(distance, 1);
+        }
+    }
+}
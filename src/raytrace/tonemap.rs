@@ -0,0 +1,195 @@
+use super::vec::Vec3;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ToneMapper {
+    Linear,
+    Reinhard,
+    ExtendedReinhard,
+    Aces,
+    Uncharted2,
+}
+
+impl ToneMapper {
+    pub const NAMES: &'static [&'static str] = &[
+        "linear",
+        "reinhard",
+        "extended-reinhard",
+        "aces",
+        "uncharted2",
+    ];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "linear" => Some(ToneMapper::Linear),
+            "reinhard" => Some(ToneMapper::Reinhard),
+            "extended-reinhard" => Some(ToneMapper::ExtendedReinhard),
+            "aces" => Some(ToneMapper::Aces),
+            "uncharted2" => Some(ToneMapper::Uncharted2),
+            _ => None,
+        }
+    }
+}
+
+// Maps scene-referred radiance to display values for 8-bit output.
+#[derive(Clone, Copy, Debug)]
+pub struct ToneMapping {
+    // Exposure adjustment in stops; each +1 doubles the radiance.
+    pub exposure: f32,
+    pub operator: ToneMapper,
+    // Luminance that extended Reinhard maps to white.
+    pub white_point: f32,
+}
+
+impl Default for ToneMapping {
+    fn default() -> Self {
+        ToneMapping {
+            exposure: 0.0,
+            operator: ToneMapper::Linear,
+            white_point: 4.0,
+        }
+    }
+}
+
+impl ToneMapping {
+    // Exposed and tone mapped color, still linear, in [0, 1].
+    pub fn apply(&self, c: Vec3) -> Vec3 {
+        let c = c * 2f32.powf(self.exposure);
+        let c = Vec3::new(c.r().max(0.0), c.g().max(0.0), c.b().max(0.0));
+        let mapped = match self.operator {
+            ToneMapper::Linear => c,
+            ToneMapper::Reinhard => scale_luminance(c, |l| l / (1.0 + l)),
+            ToneMapper::ExtendedReinhard => {
+                let white2 = self.white_point * self.white_point;
+                scale_luminance(c, |l| l * (1.0 + l / white2) / (1.0 + l))
+            }
+            ToneMapper::Aces => map_channels(c, aces),
+            ToneMapper::Uncharted2 => {
+                let white_scale = 1.0 / uncharted2(11.2);
+                map_channels(c, |x| uncharted2(2.0 * x) * white_scale)
+            }
+        };
+        map_channels(mapped, |x| x.clamp(0.0, 1.0))
+    }
+
+    // Display-encoded 8-bit sRGB.
+    pub fn encode(&self, c: Vec3) -> [u8; 3] {
+        let c = self.apply(c);
+        [
+            quantize(srgb_oetf(c.r())),
+            quantize(srgb_oetf(c.g())),
+            quantize(srgb_oetf(c.b())),
+        ]
+    }
+}
+
+pub fn luminance(c: Vec3) -> f32 {
+    0.2126 * c.r() + 0.7152 * c.g() + 0.0722 * c.b()
+}
+
+// The piecewise sRGB transfer function from IEC 61966-2-1, for linear values in [0, 1].
+pub fn srgb_oetf(x: f32) -> f32 {
+    if x <= 0.003_130_8 {
+        12.92 * x
+    } else {
+        1.055 * x.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+// Inverse of `srgb_oetf`, decoding sRGB values in [0, 1] to linear.
+pub fn srgb_eotf(x: f32) -> f32 {
+    if x <= 0.040_45 {
+        x / 12.92
+    } else {
+        ((x + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn quantize(x: f32) -> u8 {
+    (x * 255.0 + 0.5).clamp(0.0, 255.0) as u8
+}
+
+fn map_channels<F: Fn(f32) -> f32>(c: Vec3, f: F) -> Vec3 {
+    Vec3::new(f(c.r()), f(c.g()), f(c.b()))
+}
+
+// Applies a curve to luminance and scales the color to match, which keeps hues intact.
+fn scale_luminance<F: Fn(f32) -> f32>(c: Vec3, f: F) -> Vec3 {
+    let l = luminance(c);
+    if l <= 0.0 {
+        return c;
+    }
+    c * (f(l) / l)
+}
+
+// Narkowicz's fit of the ACES reference rendering transform.
+fn aces(x: f32) -> f32 {
+    (x * (2.51 * x + 0.03)) / (x * (2.43 * x + 0.59) + 0.14)
+}
+
+// Hable's filmic curve from Uncharted 2.
+fn uncharted2(x: f32) -> f32 {
+    let (a, b, c, d, e, f) = (0.15, 0.50, 0.10, 0.20, 0.02, 0.30);
+    ((x * (a * x + c * b) + d * e) / (x * (a * x + b) + d * f)) - e / f
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn srgb_round_trips() {
+        for i in 0..=1000 {
+            let x = i as f32 / 1000.0;
+            assert!((srgb_eotf(srgb_oetf(x)) - x).abs() < 1e-5, "{}", x);
+            assert!((srgb_oetf(srgb_eotf(x)) - x).abs() < 1e-5, "{}", x);
+        }
+    }
+
+    #[test]
+    fn every_8_bit_code_survives_decoding() {
+        let mapping = ToneMapping::default();
+        for code in 0..=255u8 {
+            let linear = srgb_eotf(code as f32 / 255.0);
+            let encoded = mapping.encode(Vec3::new(linear, linear, linear));
+            assert_eq!(encoded, [code; 3]);
+        }
+    }
+
+    #[test]
+    fn srgb_pieces_meet() {
+        assert!((srgb_oetf(0.003_130_8) - 0.040_45).abs() < 1e-4);
+        assert_eq!(srgb_oetf(0.0), 0.0);
+        assert!((srgb_oetf(1.0) - 1.0).abs() < 1e-6);
+        assert!((srgb_oetf(0.214_04) - 0.5).abs() < 1e-4);
+    }
+
+    #[test]
+    fn encode_exposes_then_quantizes() {
+        let mapping = ToneMapping::default();
+        assert_eq!(mapping.encode(Vec3::new(0.0, 0.216, 7.0)), [0, 128, 255]);
+        let brighter = ToneMapping {
+            exposure: 1.0,
+            ..mapping
+        };
+        assert_eq!(brighter.encode(Vec3::new(0.108, 0.0, 0.0))[0], 128);
+    }
+
+    #[test]
+    fn operators_stay_in_range_and_keep_black() {
+        for name in ToneMapper::NAMES {
+            let mapping = ToneMapping {
+                operator: ToneMapper::from_name(name).unwrap(),
+                ..ToneMapping::default()
+            };
+            let black = mapping.apply(Vec3::new(0.0, 0.0, 0.0));
+            assert!(black.r().max(black.g()).max(black.b()) < 1e-6, "{}", name);
+            let mut last = 0.0;
+            for i in 1..200 {
+                let x = i as f32 * 0.1;
+                let c = mapping.apply(Vec3::new(x, x, x));
+                assert!(c.g() >= last && c.g() <= 1.0, "{} at {}", name, x);
+                last = c.g();
+            }
+        }
+    }
+}
@@ -1,35 +1,47 @@
 use super::aabb::AABB;
 use super::hitable::{HitRecord, Hitable};
+use super::light::{area_to_solid_angle, Light};
 use super::material::Material;
 use super::ray::Ray;
 use super::vec::Vec3;
 
 use std::sync::Arc;
 
+#[derive(Clone)]
 pub struct Triangle {
     v0: Vec3,
     v1: Vec3,
     v2: Vec3,
     normal: Vec3,
+    geometric_normal: Vec3,
     material: Arc<dyn Material>,
 }
 
 impl Triangle {
     pub fn new(v0: Vec3, v1: Vec3, v2: Vec3, material: Arc<dyn Material>) -> Self {
+        let normal = Vec3::cross(v1 - v0, v2 - v0).normalize();
         Triangle {
             v0,
             v1,
             v2,
-            normal: Vec3::cross(v1 - v0, v2 - v0),
+            normal,
+            geometric_normal: normal,
             material,
         }
     }
-    pub fn new_normal(v0: Vec3, v1: Vec3, v2: Vec3, normal: Vec3, material: Arc<dyn Material>) -> Self {
+    pub fn new_normal(
+        v0: Vec3,
+        v1: Vec3,
+        v2: Vec3,
+        normal: Vec3,
+        material: Arc<dyn Material>,
+    ) -> Self {
         Triangle {
             v0,
             v1,
             v2,
             normal,
+            geometric_normal: Vec3::cross(v1 - v0, v2 - v0).normalize(),
             material,
         }
     }
@@ -44,7 +56,7 @@ impl Hitable for Triangle {
         let h = Vec3::cross(r.direction, edge2);
         let a = Vec3::dot(&edge1, &h);
 
-        if a.abs() < std::f32::EPSILON {
+        if a.abs() < f32::EPSILON {
             return None;
         }
 
@@ -52,7 +64,7 @@ impl Hitable for Triangle {
         let s = r.origin - v0;
         let u = f * Vec3::dot(&s, &h);
 
-        if u < 0.0 || u > 1.0 {
+        if !(0.0..=1.0).contains(&u) {
             return None;
         }
 
@@ -73,6 +85,7 @@ impl Hitable for Triangle {
             v,
             p: r.point_at_parameter(t),
             normal: self.normal,
+            geometric_normal: self.geometric_normal,
             material: self.material.clone(),
         })
     }
@@ -90,4 +103,32 @@ impl Hitable for Triangle {
             ),
         })
     }
+    fn collect_lights(&self, lights: &mut Vec<Arc<dyn Light>>) {
+        if self.material.is_emissive() {
+            lights.push(Arc::new(self.clone()));
+        }
+    }
+    fn collect_materials(&self, materials: &mut Vec<Arc<dyn Material>>) {
+        materials.push(Arc::clone(&self.material));
+    }
+}
+
+impl Light for Triangle {
+    fn pdf_value(&self, origin: Vec3, direction: Vec3) -> f32 {
+        match self.hit(Ray::new(origin, direction, 0.0), 0.001, f32::MAX) {
+            Some(rec) => {
+                let geometric_normal = Vec3::cross(self.v1 - self.v0, self.v2 - self.v0);
+                let area = 0.5 * geometric_normal.length();
+                area_to_solid_angle(area, rec.t, direction, geometric_normal)
+            }
+            None => 0.0,
+        }
+    }
+    fn random(&self, origin: Vec3, u: (f32, f32)) -> Vec3 {
+        let su = u.0.sqrt();
+        let b0 = 1.0 - su;
+        let b1 = u.1 * su;
+        let point = b0 * self.v0 + b1 * self.v1 + (1.0 - b0 - b1) * self.v2;
+        point - origin
+    }
 }
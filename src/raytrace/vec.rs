@@ -1,6 +1,3 @@
-extern crate rand;
-use rand::Rng;
-
 use std::f32::consts::PI;
 
 #[derive(Copy, Clone, Debug)]
@@ -59,6 +56,10 @@ impl Vec3 {
         Vec3::new(1.0 / self.x(), 1.0 / self.y(), 1.0 / self.z())
     }
 
+    pub fn max_component(&self) -> f32 {
+        self.e[0].max(self.e[1]).max(self.e[2])
+    }
+
     pub fn normalize(&self) -> Vec3 {
         let inv_len = self.length().recip();
         Vec3::new(self.x() * inv_len, self.y() * inv_len, self.z() * inv_len)
@@ -223,18 +224,45 @@ impl std::ops::DivAssign<f32> for Vec3 {
     }
 }
 
-pub fn drand48() -> f32 {
-    let random_float: f32 = rand::thread_rng().gen::<f32>();
-    random_float
+// The helpers below map uniform samples in [0, 1) to the named distribution, so that
+// stratified and low-discrepancy samples keep their structure.
+pub fn random_in_unit_sphere(u: (f32, f32), radius: f32) -> Vec3 {
+    radius.cbrt() * random_unit_vector(u)
+}
+
+// Uniform point on the unit disk in the xy plane.
+pub fn random_in_unit_disk(u: (f32, f32)) -> Vec3 {
+    let r = u.0.sqrt();
+    let phi = 2.0 * PI * u.1;
+    Vec3::new(r * phi.cos(), r * phi.sin(), 0.0)
+}
+
+pub fn random_unit_vector(u: (f32, f32)) -> Vec3 {
+    let z = 1.0 - 2.0 * u.0;
+    let r = f32::sqrt((1.0 - z * z).max(0.0));
+    let phi = 2.0 * PI * u.1;
+    Vec3::new(r * phi.cos(), r * phi.sin(), z)
+}
+
+// Cosine-weighted direction around +z.
+pub fn random_cosine_direction(u: (f32, f32)) -> Vec3 {
+    let (r1, r2) = u;
+    let phi = 2.0 * PI * r1;
+    let r = r2.sqrt();
+    Vec3::new(r * phi.cos(), r * phi.sin(), (1.0 - r2).max(0.0).sqrt())
 }
 
-pub fn random_in_unit_sphere() -> Vec3 {
-    let mut p: Vec3;
-    while {
-        p = 2.0 * Vec3::new(drand48(), drand48(), drand48()) - Vec3::new(1.0, 1.0, 1.0);
-        p.squared_length() >= 1.0
-    } {}
-    p
+// Orthonormal basis around `w`, as (u, v, w).
+pub fn onb(w: Vec3) -> (Vec3, Vec3, Vec3) {
+    let w = w.normalize();
+    let a = if w.x().abs() > 0.9 {
+        Vec3::new(0.0, 1.0, 0.0)
+    } else {
+        Vec3::new(1.0, 0.0, 0.0)
+    };
+    let v = Vec3::cross(w, a).normalize();
+    let u = Vec3::cross(w, v);
+    (u, v, w)
 }
 
 pub fn get_sphere_uv(p: Vec3) -> (f32, f32) {
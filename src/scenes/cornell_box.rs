@@ -21,10 +21,9 @@ pub fn cornell_box() -> HitableList {
     let white = Arc::new(Lambertian::new(Box::new(ConstantTexture::new(Vec3::new(
         0.73, 0.73, 0.73,
     )))));
-    let light = Arc::new(DiffuseLight::new(
-        Box::new(ConstantTexture::new(Vec3::new(15.0, 15.0, 15.0))),
-        Vec3::new(228.0, 0.0, 343.0),
-    ));
+    let light = Arc::new(DiffuseLight::new(Box::new(ConstantTexture::new(
+        Vec3::new(15.0, 15.0, 15.0),
+    ))));
     world.add(FlipNormal::new(Box::new(YZ::new(
         0.0, 555.0, 0.0, 555.0, 555.0, green,
     ))));
@@ -6,14 +6,15 @@ use crate::raytrace::{
     hitable_list::HitableList,
     material::{Dielectric, Lambertian, Metal},
     moving_sphere::Movingsphere,
+    rng::Rng,
     sphere::Sphere,
     texture::{ConstantTexture, ImageTexture, NoiseTexture},
-    vec::{drand48, Vec3},
+    vec::Vec3,
 };
 use image;
 use std::sync::Arc;
 
-pub fn final_scene() -> HitableList {
+pub fn final_scene(rng: &mut Rng) -> HitableList {
     let mut world = HitableList::new(30);
     let mut boxlist: Vec<Box<dyn Hitable>> = vec![];
     let mut boxlist2: Vec<Box<dyn Hitable>> = vec![];
@@ -38,7 +39,7 @@ pub fn final_scene() -> HitableList {
             let z0 = -1000.0 + j * w;
             let y0 = 0.0;
             let x1 = x0 + w;
-            let y1 = 100.0 * (drand48() + 0.01);
+            let y1 = 100.0 * (rng.drand48() + 0.01);
             let z1 = z0 + w;
             boxlist.push(Box::new(Cube::new(
                 Vec3::new(x0, y0, z0),
@@ -49,7 +50,7 @@ pub fn final_scene() -> HitableList {
             )));
         }
     }
-    world.add(BVHNode::construct(boxlist, 0.0, 1.0));
+    world.add(BVHNode::construct(boxlist, 0.0, 1.0, rng));
     // world.add(Box::new(XZ::new(
     //     123.0,
     //     423.0,
@@ -112,12 +113,16 @@ pub fn final_scene() -> HitableList {
     world.add(Box::new(Sphere::new(
         Vec3::new(220.0, 280.0, 300.0),
         80.0,
-        Arc::new(Lambertian::new(Box::new(NoiseTexture::new(0.1)))),
+        Arc::new(Lambertian::new(Box::new(NoiseTexture::new(0.1, rng)))),
     )));
     let ns = 750;
     for _j in 0..ns {
         boxlist2.push(Box::new(Sphere::new(
-            Vec3::new(165.0 * drand48(), 165.0 * drand48(), 165.0 * drand48()),
+            Vec3::new(
+                165.0 * rng.drand48(),
+                165.0 * rng.drand48(),
+                165.0 * rng.drand48(),
+            ),
             10.0,
             Arc::new(Lambertian::new(Box::new(ConstantTexture::new(Vec3::new(
                 0.73, 0.73, 0.73,
@@ -125,7 +130,7 @@ pub fn final_scene() -> HitableList {
         )));
     }
     world.add(Translate::new(
-        RotateY::new(BVHNode::construct(boxlist2, 0.0, 1.0), 15.0),
+        RotateY::new(BVHNode::construct(boxlist2, 0.0, 1.0, rng), 15.0),
         Vec3::new(-100.0, 270.0, 395.0),
     ));
     world
@@ -7,3 +7,146 @@ pub mod perlin_spheres;
 pub mod random_spheres;
 pub mod textured_sphere;
 pub mod triangle_scene;
+
+use crate::raytrace::environment::{Environment, GradientEnvironment, NoEnvironment};
+use crate::raytrace::{hitable_list::HitableList, rng::Rng, vec::Vec3};
+
+use std::sync::Arc;
+
+pub struct Scene {
+    pub world: HitableList,
+    pub look_from: Vec3,
+    pub look_at: Vec3,
+    pub vfov: f32,
+    pub aperture: f32,
+    pub focus_dist: f32,
+    // What rays leaving the scene see: the outdoor scenes have a sky, closed or self-lit ones
+    // are black.
+    pub environment: Arc<dyn Environment>,
+}
+
+pub const SCENE_NAMES: &[&str] = &[
+    "default_scene",
+    "random_scene",
+    "moving_random_scene",
+    "checkered_texture",
+    "perlin_spheres",
+    "lighted_perlin_spheres",
+    "textured_spheres",
+    "cornell_box",
+    "cornell_smoke",
+    "final_scene",
+    "triangle_scene",
+];
+
+// Randomly generated scenes are fully determined by `seed`.
+pub fn load(name: &str, seed: u64) -> Option<Scene> {
+    let mut rng = Rng::new(seed);
+    let cornell_from = Vec3::new(278.0, 278.0, -800.0);
+    let cornell_at = Vec3::new(278.0, 278.0, 0.0);
+    let outdoor_from = Vec3::new(13.0, 2.0, 3.0);
+    let origin = Vec3::new(0.0, 0.0, 0.0);
+    let scene = match name {
+        "default_scene" => Scene {
+            world: default_scene::default_scene(),
+            look_from: Vec3::new(3.0, 3.0, 2.0),
+            look_at: Vec3::new(0.0, 0.0, -1.0),
+            vfov: 20.0,
+            aperture: 0.0,
+            focus_dist: (Vec3::new(3.0, 3.0, 2.0) - Vec3::new(0.0, 0.0, -1.0)).length(),
+            environment: Arc::new(GradientEnvironment::default()),
+        },
+        "random_scene" => Scene {
+            world: random_spheres::random_scene(&mut rng),
+            look_from: outdoor_from,
+            look_at: origin,
+            vfov: 20.0,
+            aperture: 0.1,
+            focus_dist: 10.0,
+            environment: Arc::new(GradientEnvironment::default()),
+        },
+        "moving_random_scene" => Scene {
+            world: random_spheres::moving_random_scene(&mut rng),
+            look_from: outdoor_from,
+            look_at: origin,
+            vfov: 20.0,
+            aperture: 0.0,
+            focus_dist: 10.0,
+            environment: Arc::new(GradientEnvironment::default()),
+        },
+        "checkered_texture" => Scene {
+            world: checkered_texture::checkered_texture_scene(),
+            look_from: outdoor_from,
+            look_at: origin,
+            vfov: 20.0,
+            aperture: 0.0,
+            focus_dist: 10.0,
+            environment: Arc::new(GradientEnvironment::default()),
+        },
+        "perlin_spheres" => Scene {
+            world: perlin_spheres::perlin_spheres(&mut rng),
+            look_from: outdoor_from,
+            look_at: origin,
+            vfov: 20.0,
+            aperture: 0.0,
+            focus_dist: 10.0,
+            environment: Arc::new(GradientEnvironment::default()),
+        },
+        "lighted_perlin_spheres" => Scene {
+            world: lighted_perlin_spheres::lightted_perlin_spheres(&mut rng),
+            look_from: Vec3::new(26.0, 3.0, 6.0),
+            look_at: Vec3::new(0.0, 2.0, 0.0),
+            vfov: 20.0,
+            aperture: 0.0,
+            focus_dist: 10.0,
+            environment: Arc::new(NoEnvironment),
+        },
+        "textured_spheres" => Scene {
+            world: textured_sphere::textured_spheres(),
+            look_from: outdoor_from,
+            look_at: Vec3::new(0.0, 2.0, 0.0),
+            vfov: 20.0,
+            aperture: 0.0,
+            focus_dist: 10.0,
+            environment: Arc::new(GradientEnvironment::default()),
+        },
+        "cornell_box" => Scene {
+            world: cornell_box::cornell_box(),
+            look_from: cornell_from,
+            look_at: cornell_at,
+            vfov: 40.0,
+            aperture: 0.0,
+            focus_dist: 10.0,
+            environment: Arc::new(NoEnvironment),
+        },
+        "cornell_smoke" => Scene {
+            world: cornell_box::cornell_smoke(),
+            look_from: cornell_from,
+            look_at: cornell_at,
+            vfov: 40.0,
+            aperture: 0.0,
+            focus_dist: 10.0,
+            environment: Arc::new(NoEnvironment),
+        },
+        "final_scene" => Scene {
+            world: final_scene::final_scene(&mut rng),
+            look_from: Vec3::new(478.0, 278.0, -600.0),
+            look_at: cornell_at,
+            vfov: 40.0,
+            aperture: 0.0,
+            focus_dist: 10.0,
+            environment: Arc::new(NoEnvironment),
+        },
+        "triangle_scene" => Scene {
+            world: triangle_scene::triangle_scene(&mut rng),
+            look_from: cornell_from,
+            look_at: cornell_at,
+            vfov: 40.0,
+            aperture: 0.0,
+            focus_dist: 10.0,
+            environment: Arc::new(NoEnvironment),
+        },
+        _ => return None,
+    };
+    Some(scene)
+}
@@ -4,14 +4,15 @@ use crate::raytrace::{
     hitable_list::HitableList,
     material::{Dielectric, Lambertian, Metal},
     moving_sphere::Movingsphere,
+    rng::Rng,
     sphere::Sphere,
     texture::ConstantTexture,
-    vec::{drand48, Vec3},
+    vec::Vec3,
 };
 
 use std::sync::Arc;
 
-pub fn random_scene() -> HitableList {
+pub fn random_scene(rng: &mut Rng) -> HitableList {
     let n = 500;
     let mut world = HitableList::new(n + 1);
     let mut spherelist: Vec<Box<dyn Hitable>> = vec![];
@@ -25,17 +26,21 @@ pub fn random_scene() -> HitableList {
 
     for a in -11..11 {
         for b in -11..11 {
-            let choose_mat = drand48();
-            let center = Vec3::new(a as f32 + 0.9 * drand48(), 0.2, b as f32 + 0.9 * drand48());
+            let choose_mat = rng.drand48();
+            let center = Vec3::new(
+                a as f32 + 0.9 * rng.drand48(),
+                0.2,
+                b as f32 + 0.9 * rng.drand48(),
+            );
             if (center - Vec3::new(4.0, 0.2, 0.0)).length() > 0.9 {
                 if choose_mat < 0.8 {
                     spherelist.push(Box::new(Sphere::new(
                         center,
                         0.2,
                         Arc::new(Lambertian::new(Box::new(ConstantTexture::new(Vec3::new(
-                            drand48() * drand48(),
-                            drand48() * drand48(),
-                            drand48() * drand48(),
+                            rng.drand48() * rng.drand48(),
+                            rng.drand48() * rng.drand48(),
+                            rng.drand48() * rng.drand48(),
                         ))))),
                     )));
                 } else if choose_mat < 0.95 {
@@ -44,11 +49,11 @@ pub fn random_scene() -> HitableList {
                         0.2,
                         Arc::new(Metal::new(
                             Vec3::new(
-                                0.5 * (1.0 + drand48()),
-                                0.5 * (1.0 + drand48()),
-                                0.5 * (1.0 + drand48()),
+                                0.5 * (1.0 + rng.drand48()),
+                                0.5 * (1.0 + rng.drand48()),
+                                0.5 * (1.0 + rng.drand48()),
                             ),
-                            0.5 * (1.0 + drand48()),
+                            0.5 * (1.0 + rng.drand48()),
                         )),
                     )));
                 } else {
@@ -61,7 +66,7 @@ pub fn random_scene() -> HitableList {
             }
         }
     }
-    world.add(BVHNode::construct(spherelist, 0.0, 1.0));
+    world.add(BVHNode::construct(spherelist, 0.0, 1.0, rng));
     world.add(Box::new(Sphere::new(
         Vec3::new(0.0, 1.0, 0.0),
         1.0,
@@ -83,7 +88,7 @@ pub fn random_scene() -> HitableList {
     world
 }
 
-pub fn moving_random_scene() -> HitableList {
+pub fn moving_random_scene(rng: &mut Rng) -> HitableList {
     let n = 500;
     let mut world = HitableList::new(n + 1);
     world.add(Box::new(Sphere::new(
@@ -96,20 +101,24 @@ pub fn moving_random_scene() -> HitableList {
 
     for a in -11..11 {
         for b in -11..11 {
-            let choose_mat = drand48();
-            let center = Vec3::new(a as f32 + 0.9 * drand48(), 0.2, b as f32 + 0.9 * drand48());
+            let choose_mat = rng.drand48();
+            let center = Vec3::new(
+                a as f32 + 0.9 * rng.drand48(),
+                0.2,
+                b as f32 + 0.9 * rng.drand48(),
+            );
             if (center - Vec3::new(4.0, 0.2, 0.0)).length() > 0.9 {
                 if choose_mat < 0.8 {
                     world.add(Box::new(Movingsphere::new(
                         center,
-                        center + Vec3::new(0.0, 0.5 * drand48(), 0.0),
+                        center + Vec3::new(0.0, 0.5 * rng.drand48(), 0.0),
                         0.0,
                         1.0,
                         0.2,
                         Arc::new(Lambertian::new(Box::new(ConstantTexture::new(Vec3::new(
-                            drand48() * drand48(),
-                            drand48() * drand48(),
-                            drand48() * drand48(),
+                            rng.drand48() * rng.drand48(),
+                            rng.drand48() * rng.drand48(),
+                            rng.drand48() * rng.drand48(),
                         ))))),
                     )));
                 } else if choose_mat < 0.95 {
@@ -118,11 +127,11 @@ pub fn moving_random_scene() -> HitableList {
                         0.2,
                         Arc::new(Metal::new(
                             Vec3::new(
-                                0.5 * (1.0 + drand48()),
-                                0.5 * (1.0 + drand48()),
-                                0.5 * (1.0 + drand48()),
+                                0.5 * (1.0 + rng.drand48()),
+                                0.5 * (1.0 + rng.drand48()),
+                                0.5 * (1.0 + rng.drand48()),
                             ),
-                            0.5 * (1.0 + drand48()),
+                            0.5 * (1.0 + rng.drand48()),
                         )),
                     )));
                 } else {
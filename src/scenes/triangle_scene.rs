@@ -6,6 +6,7 @@ use crate::raytrace::{
     matrix::Matrix44,
     mesh::hitable_mesh,
     rectangle::{FlipNormal, XY, XZ, YZ},
+    rng::Rng,
     texture::ConstantTexture,
     triangle::Triangle,
     vec::Vec3,
@@ -13,7 +14,7 @@ use crate::raytrace::{
 use std::path::Path;
 use std::sync::Arc;
 
-pub fn triangle_scene() -> HitableList {
+pub fn triangle_scene(rng: &mut Rng) -> HitableList {
     let mut world = HitableList::new(8);
     let bunny = hitable_mesh(
         Path::new("bunny.obj"),
@@ -45,8 +46,8 @@ pub fn triangle_scene() -> HitableList {
         )),
         Vec3::new(200.0, 0.0, 300.0),
     ));
-    world.add(BVHNode::construct(bunny, 0.0, 1.0));
-    world.add(BVHNode::construct(suzanne, 0.0, 1.0));
+    world.add(BVHNode::construct(bunny, 0.0, 1.0, rng));
+    world.add(BVHNode::construct(suzanne, 0.0, 1.0, rng));
     let red = Arc::new(Lambertian::new(Box::new(ConstantTexture::new(Vec3::new(
         0.65, 0.05, 0.05,
     )))));
@@ -56,10 +57,9 @@ pub fn triangle_scene() -> HitableList {
     let _white = Arc::new(Lambertian::new(Box::new(ConstantTexture::new(Vec3::new(
         0.73, 0.73, 0.73,
     )))));
-    let light = Arc::new(DiffuseLight::new(
-        Box::new(ConstantTexture::new(Vec3::new(15.0, 15.0, 15.0))),
-        Vec3::new(228.0, 0.0, 343.0),
-    ));
+    let light = Arc::new(DiffuseLight::new(Box::new(ConstantTexture::new(
+        Vec3::new(15.0, 15.0, 15.0),
+    ))));
     world.add(FlipNormal::new(Box::new(YZ::new(
         0.0, 555.0, 0.0, 555.0, 555.0, red,
     ))));